@@ -10,11 +10,7 @@ struct Uniform {
 @group(0) @binding(0) 
 var<uniform> uni: Uniform;
 
-struct CameraUniform {
-    proj: mat4x4<f32>,
-};
-@group(1) @binding(0) 
-var<uniform> cam_uni: CameraUniform;
+#include cat_render::camera
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
@@ -0,0 +1,195 @@
+//! Derive macros of cat_render, use them through `cat_render::render::buffer`
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields, FieldsNamed, Ident, LitInt};
+
+/// `VertexBufferLayout` from fields.
+///
+/// Locations go in order from 0, `#[cat_vertex(location = n)]` on field sets it
+/// for field and next ones. Format is from type, `#[cat_vertex(format = Unorm8x4)]` sets it.
+/// `#[cat_vertex(instance)]` on struct makes instance step mode.
+#[proc_macro_derive(CatVertex, attributes(cat_vertex))]
+pub fn derive_cat_vertex(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    cat_vertex(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Checks at compile time that fields are on same offsets as in WGSL uniform.
+/// Fields with names from `_` are padding and are not in WGSL struct
+#[proc_macro_derive(CatUniform)]
+pub fn derive_cat_uniform(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    cat_uniform(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn named_fields(input: &DeriveInput) -> syn::Result<&FieldsNamed> {
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "generic structs are not supported",
+        ));
+    }
+    match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => Ok(fields),
+            _ => Err(Error::new_spanned(
+                &input.ident,
+                "only structs with named fields are supported",
+            )),
+        },
+        _ => Err(Error::new_spanned(
+            &input.ident,
+            "only structs are supported",
+        )),
+    }
+}
+
+fn cat_vertex(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let name = &input.ident;
+    let fields = named_fields(&input)?;
+
+    let mut instance = false;
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|a| a.path().is_ident("cat_vertex"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("instance") {
+                instance = true;
+                Ok(())
+            } else {
+                Err(meta.error("expected `instance`"))
+            }
+        })?;
+    }
+
+    let mut pushes = Vec::new();
+    for field in fields.named.iter() {
+        let ident = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        let mut location = None;
+        let mut format: Option<Ident> = None;
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|a| a.path().is_ident("cat_vertex"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("location") {
+                    let lit: LitInt = meta.value()?.parse()?;
+                    location = Some(lit.base10_parse::<u32>()?);
+                    Ok(())
+                } else if meta.path.is_ident("format") {
+                    format = Some(meta.value()?.parse()?);
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `location` or `format`"))
+                }
+            })?;
+        }
+        if let Some(location) = location {
+            pushes.push(quote! { location = #location; });
+        }
+        let formats = match format {
+            Some(format) => quote! { &[::cat_render::wgpu::VertexFormat::#format] },
+            None => quote! { <#ty as ::cat_render::render::buffer::VertexField>::FORMATS },
+        };
+        pushes.push(quote! {
+            let mut offset = ::std::mem::offset_of!(#name, #ident) as u64;
+            for format in #formats {
+                attributes.push(::cat_render::wgpu::VertexAttribute {
+                    format: *format,
+                    offset,
+                    shader_location: location,
+                });
+                offset += format.size();
+                location += 1;
+            }
+        });
+    }
+
+    let step_mode = if instance {
+        quote! { ::cat_render::wgpu::VertexStepMode::Instance }
+    } else {
+        quote! { ::cat_render::wgpu::VertexStepMode::Vertex }
+    };
+    Ok(quote! {
+        impl ::cat_render::render::buffer::CatVertex for #name {
+            const STEP_MODE: ::cat_render::wgpu::VertexStepMode = #step_mode;
+            fn attributes() -> &'static [::cat_render::wgpu::VertexAttribute] {
+                static ATTRIBUTES: ::std::sync::LazyLock<::std::vec::Vec<::cat_render::wgpu::VertexAttribute>> =
+                    ::std::sync::LazyLock::new(|| {
+                        let mut attributes = ::std::vec::Vec::new();
+                        let mut location = 0u32;
+                        #(#pushes)*
+                        let _ = location;
+                        attributes
+                    });
+                &ATTRIBUTES
+            }
+        }
+    })
+}
+
+fn cat_uniform(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let name = &input.ident;
+    let fields = named_fields(&input)?;
+
+    let mut checks = Vec::new();
+    for field in fields.named.iter() {
+        let ident = field.ident.as_ref().unwrap();
+        if ident.to_string().starts_with('_') {
+            continue;
+        }
+        let ty = &field.ty;
+        let message = format!(
+            "field `{}` of `{}` is not on WGSL offset, add padding before it",
+            ident, name
+        );
+        checks.push(quote! {
+            let align = <#ty as ::cat_render::render::buffer::CatUniform>::WGSL_ALIGN;
+            offset = ::cat_render::render::buffer::wgsl_round_up(align, offset);
+            assert!(::std::mem::offset_of!(#name, #ident) == offset, #message);
+            offset += <#ty as ::cat_render::render::buffer::CatUniform>::WGSL_SIZE;
+            if align > max_align {
+                max_align = align;
+            }
+        });
+    }
+    let size_message = format!(
+        "size of `{}` is not same as in WGSL, add padding at end",
+        name
+    );
+    Ok(quote! {
+        #[allow(unused_mut, unused_assignments)]
+        impl ::cat_render::render::buffer::CatUniform for #name {
+            // Structs inside uniform are aligned to 16
+            const WGSL_ALIGN: usize = ::cat_render::render::buffer::wgsl_round_up(16, {
+                let mut offset = 0usize;
+                let mut max_align = 1usize;
+                #(#checks)*
+                let _ = offset;
+                max_align
+            });
+            const WGSL_SIZE: usize = {
+                let mut offset = 0usize;
+                let mut max_align = 1usize;
+                #(#checks)*
+                assert!(
+                    ::std::mem::size_of::<#name>()
+                        == ::cat_render::render::buffer::wgsl_round_up(max_align, offset),
+                    #size_message
+                );
+                ::cat_render::render::buffer::wgsl_round_up(Self::WGSL_ALIGN, offset)
+            };
+        }
+        // Checks are done when constant is evaluated
+        const _: usize = <#name as ::cat_render::render::buffer::CatUniform>::WGSL_SIZE;
+    })
+}
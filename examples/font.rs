@@ -2,6 +2,7 @@ use bytemuck::{Pod, Zeroable};
 use cat_render::{
     prelude::*,
     render::{
+        buffer::{CatUniform, CatVertex},
         camera::{Camera, Camera2D, Camera2DOptions},
         mesh::{Material, MaterialLayoutBuilder, Mesh},
         render_pipeline::PipelineOptions,
@@ -27,6 +28,7 @@ impl CatApp for App {
     fn config() -> AppConfig {
         AppConfig {
             loop_type: LoopType::Active,
+            ..Default::default()
         }
     }
     fn new(context: &mut AppContext) -> Self {
@@ -65,7 +67,9 @@ impl CatApp for App {
         });
         material_layout.register_uniform_at(0, ShaderStages::VERTEX_FRAGMENT);
         material_layout.register_texture_at(1, 2, ShaderStages::VERTEX_FRAGMENT);
-        let material_layout = material_layout.build(context.get_mut_renderer());
+        let material_layout = material_layout
+            .build(context.get_mut_renderer())
+            .unwrap_or_else(|e| panic!("{}", e));
 
         let view_proj = glam::Mat4::from_scale(Vec3::new(2., 2.0, 0.))
             * glam::Mat4::from_translation(Vec3::new(0.0, -5.0, 0.));
@@ -146,28 +150,14 @@ impl CatApp for App {
     }
 }
 #[repr(C)]
-#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable, CatVertex)]
 struct Vertex {
     position: [f32; 3],
     tex_coords: [f32; 2],
 }
-impl Vertex {
-    const ATTRIBS: [wgpu::VertexAttribute; 2] =
-        wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x2];
-
-    fn desc() -> wgpu::VertexBufferLayout<'static> {
-        use std::mem;
-
-        wgpu::VertexBufferLayout {
-            array_stride: mem::size_of::<Self>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &Self::ATTRIBS,
-        }
-    }
-}
 #[repr(C)]
 // This is so we can store this in a buffer
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, CatUniform)]
 struct MyUniform {
     view_proj: [[f32; 4]; 4],
 }
@@ -10,7 +10,10 @@ use cat_render::{
     utils::{
         fs::Filesystem,
         input::Input,
-        render::sprite::{Sprite, SpriteLayout},
+        render::{
+            sprite::{Sprite, SpriteLayout},
+            sprite_batch::{SpriteBatch, SpriteBatchLayout},
+        },
         timer::Timer,
     },
 };
@@ -26,14 +29,14 @@ pub struct App {
     input: Input,
     timer: Timer,
     fps_cnt: u32,
-    layout: SpriteLayout,
-    texture: Texture,
+    batch: SpriteBatch,
 }
 
 impl CatApp for App {
     fn config() -> AppConfig {
         AppConfig {
             loop_type: LoopType::Waiting,
+            ..Default::default()
         }
     }
     fn new(context: &mut AppContext) -> Self {
@@ -73,15 +76,14 @@ impl CatApp for App {
 
             sprites.push(sprite);
         }
+        let batch = SpriteBatch::new(&SpriteBatchLayout::new(context, camera.get_bind_group()));
         Self {
             camera,
             sprites,
             input: Input::new(),
             timer: Timer::new(Duration::from_secs_f32(1.)),
             fps_cnt: 0,
-
-            layout: sprite_layout,
-            texture,
+            batch,
         }
     }
     fn update(&mut self, _context: &mut AppContext, delta: f32) {
@@ -136,9 +138,7 @@ impl CatApp for App {
             None,
             |render| {
                 render.set_camera(&mut self.camera);
-                for sprite in self.sprites.iter_mut() {
-                    sprite.render(render);
-                }
+                self.batch.draw_sprites(render, self.sprites.iter());
             },
         );
     }
@@ -9,7 +9,6 @@ use cat_render::{
     },
     utils::{fs::Filesystem, input::Input},
 };
-use wgpu::ShaderStages;
 use winit::{event::WindowEvent, keyboard::KeyCode};
 
 fn main() {
@@ -27,6 +26,7 @@ impl CatApp for App {
     fn config() -> AppConfig {
         AppConfig {
             loop_type: LoopType::Active,
+            ..Default::default()
         }
     }
     fn new(context: &mut AppContext) -> Self {
@@ -48,34 +48,33 @@ impl CatApp for App {
             )
             .unwrap();
 
-        let mut material_layout = MaterialLayoutBuilder::new(PipelineOptions {
-            vertex_shader: Filesystem::get()
-                .read_to_string("assets/shader.wgsl")
-                .unwrap(),
+        // Bindings and vertex buffer are read from shader
+        let material_layout = MaterialLayoutBuilder::from_shader(PipelineOptions {
+            // Edit shader while example is running to see hot reload
+            vertex_shader_path: Some(String::from("assets/shader.wgsl")),
             vertex_entry_point: String::from("vs_main"),
             fragment_entry_point: String::from("fs_main"),
-            buffers: vec![Vertex::desc()],
             bind_group_layouts: vec![camera.get_bind_group().layout()],
             ..Default::default()
-        });
-        material_layout.register_uniform_at(0, ShaderStages::VERTEX_FRAGMENT);
-        material_layout.register_texture_at(1, 2, ShaderStages::VERTEX_FRAGMENT);
-        let material_layout = material_layout.build(context.get_mut_renderer());
+        })
+        .build(context.get_mut_renderer())
+        .unwrap_or_else(|e| panic!("{}", e));
 
         let view_proj = glam::Mat4::from_scale(Vec3::new(2., 2.0, 0.))
             * glam::Mat4::from_translation(Vec3::new(0.0, -5.0, 0.));
 
-        let material = Material::from_layout(
+        let material = Material::from_named(
             &material_layout,
             vec![(
-                0,
+                "uni",
                 bytemuck::bytes_of(&MyUniform {
                     view_proj: view_proj.to_cols_array_2d(),
                 })
                 .to_vec(),
             )],
-            vec![(1, 2, texture.clone())],
-        );
+            vec![("t_diffuse", texture.clone())],
+        )
+        .unwrap();
         #[rustfmt::skip]
         let vertices = vec![
             Vertex { position: [-8.68241, 49.240386, 0.0] , tex_coords: [0.4131759, 0.99240386], }, // A
@@ -143,24 +142,11 @@ impl CatApp for App {
 }
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Pod, Zeroable)]
+// Layout of buffer is read from shader
 struct Vertex {
     position: [f32; 3],
     tex_coords: [f32; 2],
 }
-impl Vertex {
-    const ATTRIBS: [wgpu::VertexAttribute; 2] =
-        wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x2];
-
-    fn desc() -> wgpu::VertexBufferLayout<'static> {
-        use std::mem;
-
-        wgpu::VertexBufferLayout {
-            array_stride: mem::size_of::<Self>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &Self::ATTRIBS,
-        }
-    }
-}
 #[repr(C)]
 // This is so we can store this in a buffer
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
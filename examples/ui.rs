@@ -41,6 +41,7 @@ impl CatApp for App {
     fn config() -> AppConfig {
         AppConfig {
             loop_type: LoopType::Active,
+            ..Default::default()
         }
     }
     fn new(context: &mut AppContext) -> Self {
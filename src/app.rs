@@ -10,7 +10,16 @@ pub trait CatApp {
     fn update(&mut self, context: &mut AppContext, delta: f32);
     fn new(context: &mut AppContext) -> Self;
     fn window_event(&mut self, event: WindowEvent, context: &mut AppContext, window: CatWindow);
+    /// Not called if `AppConfig::render_thread` is set, see `extract`
     fn render(&mut self, render: &mut Renderer);
+    /// Draw data of frame after `update` for render thread, it is rendered while
+    /// next `update` runs. Called only if `AppConfig::render_thread` is set
+    fn extract(&mut self, _context: &mut AppContext) -> Option<Box<dyn FrameSnapshot>> {
+        None
+    }
+    /// Device was lost and is created again. Pipelines and registered resources
+    /// (`Renderer::register*`) are recreated, everything else must be created here
+    fn device_recreated(&mut self, _context: &mut AppContext) {}
 }
 
 pub trait AppExt: CatApp {
@@ -24,9 +33,19 @@ impl<A: CatApp> AppExt for A {
     }
 }
 
+/// Immutable draw data of one frame (cameras, transforms, materials),
+/// rendered on render thread one frame after `CatApp::update`
+pub trait FrameSnapshot: Send + 'static {
+    fn render(self: Box<Self>, renderer: &mut Renderer);
+}
+
 #[derive(Default)]
 pub struct AppConfig {
     pub loop_type: LoopType,
+    /// Render frames from `CatApp::extract` on own thread, so `update` and
+    /// rendering don't wait for each other. Renderer is moved to it after
+    /// `CatApp::new`, use `AppContext::with_renderer` then
+    pub render_thread: bool,
 }
 
 /// See winit event loop modes
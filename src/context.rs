@@ -7,29 +7,58 @@ use std::{
 
 use crate::{
     app::LoopType,
-    render::{surface::SurfaceId, Renderer},
-    utils::timer::TimeStep,
+    render::{stats::RenderStats, surface::SurfaceId, RenderContext, Renderer},
+    render_thread::FrameShared,
     window::{CatWindow, WindowAttributes, Windows},
     winit::WinitContext,
 };
 
 pub(crate) struct StaticContext {
     pub windows: Windows,
-    pub renderer: Renderer,
+    /// None after it is moved to render thread, see `share_renderer`
+    pub renderer: Option<Renderer>,
+    /// Renderer, stats and resizes of render thread if `AppConfig::render_thread` is set
+    pub shared: Option<Arc<FrameShared>>,
+    /// Stats of render thread, copied before `CatApp::update`
+    pub thread_stats: RenderStats,
+    /// Context of renderer, used without waiting for frame. Changed with `recover_device`
+    pub render_context: RenderContext,
     pub fps: u32,
 }
 
 impl StaticContext {
     pub fn new() -> Self {
+        let renderer = pollster::block_on(Renderer::new());
         Self {
             fps: 120,
             windows: Windows::new(),
-            renderer: pollster::block_on(Renderer::new()),
+            render_context: renderer.context().clone(),
+            renderer: Some(renderer),
+            shared: None,
+            thread_stats: RenderStats::default(),
             // resources: Resources::new(),
         }
     }
+    /// Moves renderer for render thread
+    pub fn share_renderer(&mut self) -> Arc<FrameShared> {
+        let renderer = self.renderer.take().expect("Renderer is already shared");
+        let shared = Arc::new(FrameShared::new(renderer));
+        self.shared = Some(shared.clone());
+        shared
+    }
+    /// Locks renderer if it is on render thread
+    pub fn with_renderer<R>(&mut self, f: impl FnOnce(&mut Renderer) -> R) -> R {
+        match (&mut self.renderer, &self.shared) {
+            (Some(renderer), _) => f(renderer),
+            (None, Some(shared)) => f(&mut shared.renderer.lock().unwrap()),
+            (None, None) => unreachable!("Renderer is neither owned nor shared"),
+        }
+    }
 }
 
+const RENDERER_ON_THREAD: &str =
+    "Renderer is on render thread, use `AppContext::with_renderer` with `AppConfig::render_thread`";
+
 pub struct Resources {
     resources: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
 }
@@ -109,25 +138,44 @@ impl<'a> AppContext<'a> {
         self.base.windows.create(&self.winit_context, attrs)
     }
     pub fn destroy_window(&mut self, window: CatWindow) {
-        self.base.windows.delete(window);
+        self.base.windows.delete(window, &self.base.render_context);
     }
     pub fn exists_window(&self, window: &CatWindow) -> bool {
         self.base.windows.exists(window)
     }
     //-----------------------------RENDERER---------------------------//
+    /// Panics with `AppConfig::render_thread` after `CatApp::new`, renderer is
+    /// on render thread then. Use `with_renderer` for code which runs in both modes
     pub fn get_mut_renderer(&mut self) -> &mut Renderer {
-        &mut self.base.renderer
+        self.base.renderer.as_mut().expect(RENDERER_ON_THREAD)
     }
+    /// Panics like `get_mut_renderer`
     pub fn get_renderer(&self) -> &Renderer {
-        &self.base.renderer
+        self.base.renderer.as_ref().expect(RENDERER_ON_THREAD)
+    }
+    /// Renderer with or without render thread. With `AppConfig::render_thread`
+    /// it waits for frame which is rendered now, for resources `render_context`
+    /// is enough and doesn't wait
+    pub fn with_renderer<R>(&mut self, f: impl FnOnce(&mut Renderer) -> R) -> R {
+        self.base.with_renderer(f)
+    }
+    /// Device of renderer, resources created with it don't lock renderer
+    pub fn render_context(&self) -> RenderContext {
+        self.base.render_context.clone()
+    }
+    /// Statistics of last rendered frame. With `AppConfig::render_thread` it is
+    /// frame which was rendered before last `update`
+    pub fn render_stats(&self) -> &RenderStats {
+        match &self.base.renderer {
+            Some(renderer) => renderer.render_stats(),
+            None => &self.base.thread_stats,
+        }
     }
     //------RENDERING-----/
+    /// Doesn't lock renderer
     pub fn create_surface_for_window(&mut self, window: &CatWindow) -> Option<SurfaceId> {
-        Some(
-            self.base
-                .renderer
-                .create_surface(self.base.windows.get(window)?),
-        )
+        let window = self.base.windows.get(window)?;
+        Some(self.base.render_context.create_surface(window))
     }
     //-----------------------------RESOURCES---------------------------//
     // pub fn insert_resource<R: Any>(&mut self, res: R) {
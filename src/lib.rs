@@ -1,3 +1,7 @@
+// Derive macros use `::cat_render` paths
+extern crate self as cat_render;
+
+mod render_thread;
 pub(crate) mod winit;
 
 pub mod app;
@@ -6,6 +10,7 @@ pub mod render;
 pub mod utils;
 pub mod window;
 pub use glam::*;
+pub use wgpu;
 
 pub mod prelude {
     pub use crate::app::*;
@@ -1,24 +1,66 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, Weak};
 
-use super::UnMutRenderer;
+use super::context::RenderContext;
+
+/// Entries of layouts from `BindGroupLayout::new`,
+/// pipelines create their layouts again with them after device is lost
+static LAYOUT_ENTRIES: Mutex<Vec<LayoutEntries>> = Mutex::new(Vec::new());
+type LayoutEntries = (Weak<wgpu::BindGroupLayout>, Vec<wgpu::BindGroupLayoutEntry>);
+
+/// Layout with same entries on device of `context`, `None` if layout is not from `BindGroupLayout`
+pub(crate) fn recreate_layout(
+    context: &RenderContext,
+    layout: &Arc<wgpu::BindGroupLayout>,
+) -> Option<Arc<wgpu::BindGroupLayout>> {
+    let entries = LAYOUT_ENTRIES
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|(l, _)| l.as_ptr() == Arc::as_ptr(layout))?
+        .1
+        .clone();
+    Some(create_layout(context, entries))
+}
+
+fn create_layout(
+    context: &RenderContext,
+    entries: Vec<wgpu::BindGroupLayoutEntry>,
+) -> Arc<wgpu::BindGroupLayout> {
+    let layout = Arc::new(context.device().create_bind_group_layout(
+        &wgpu::BindGroupLayoutDescriptor {
+            entries: &entries,
+            label: None,
+        },
+    ));
+    let mut layouts = LAYOUT_ENTRIES.lock().unwrap();
+    layouts.retain(|(l, _)| l.strong_count() > 0);
+    layouts.push((Arc::downgrade(&layout), entries));
+    layout
+}
 
 /// Bind group from wgpu BindGroup
 #[derive(Clone)]
 pub struct BindGroup {
-    /// Inner
-    pub(crate) group: wgpu::BindGroup,
+    /// Inner, shared with cache
+    pub(crate) group: Arc<wgpu::BindGroup>,
     /// layout
     layout: Arc<wgpu::BindGroupLayout>,
+    context: RenderContext,
 }
 
 /// Bind group layout from wgpu BindGroupLayout
 #[derive(Clone)]
 pub struct BindGroupLayout {
     layout: Arc<wgpu::BindGroupLayout>,
+    context: RenderContext,
 }
 impl BindGroupLayout {
     /// Creates new bind group layout use for reuse pipelines.
     pub fn new(entries: Vec<BindGroupEntryLayout>) -> Self {
+        Self::new_in(&RenderContext::global(), entries)
+    }
+    /// See `new`, layout is on device of `context`
+    pub fn new_in(context: &RenderContext, entries: Vec<BindGroupEntryLayout>) -> Self {
         let mut out_entries = Vec::new();
         for entry in entries.iter() {
             out_entries.push(wgpu::BindGroupLayoutEntry {
@@ -28,25 +70,24 @@ impl BindGroupLayout {
                 count: None,
             });
         }
-        let bind_group_layout = UnMutRenderer::get().device.create_bind_group_layout(
-            &wgpu::BindGroupLayoutDescriptor {
-                entries: &out_entries,
-                label: None,
-            },
-        );
-
         Self {
-            layout: Arc::new(bind_group_layout),
+            layout: create_layout(context, out_entries),
+            context: context.clone(),
         }
     }
     /// Inner layout
     pub fn layout(&self) -> Arc<wgpu::BindGroupLayout> {
         self.layout.clone()
     }
+    /// Context on which layout is created
+    pub fn context(&self) -> &RenderContext {
+        &self.context
+    }
 }
 
 impl BindGroup {
-    /// Creates bind group
+    /// Creates bind group, same layout and resources give same cached bind group.
+    /// It is on device of layout
     pub fn new_from_layout(
         resources: Vec<BindGroupEntryResources>,
         layout: &BindGroupLayout,
@@ -58,22 +99,27 @@ impl BindGroup {
                 resource: entry.resource,
             });
         }
-        let bind_group =
-            UnMutRenderer::get()
-                .device
-                .create_bind_group(&wgpu::BindGroupDescriptor {
-                    label: None,
-                    layout: &layout.layout,
-                    entries: &out_entries,
-                });
+        let context = &layout.context;
+        let bind_group = context
+            .cache
+            .bind_group(context.device(), &layout.layout, &out_entries);
         Self {
             group: bind_group,
             layout: layout.layout.clone(),
+            context: context.clone(),
         }
     }
     /// Creates bind group with new layout
     pub fn new(layout: Vec<BindGroupEntryLayout>, resources: Vec<BindGroupEntryResources>) -> Self {
-        Self::new_from_layout(resources, &BindGroupLayout::new(layout))
+        Self::new_in(&RenderContext::global(), layout, resources)
+    }
+    /// Creates bind group with new layout on device of `context`
+    pub fn new_in(
+        context: &RenderContext,
+        layout: Vec<BindGroupEntryLayout>,
+        resources: Vec<BindGroupEntryResources>,
+    ) -> Self {
+        Self::new_from_layout(resources, &BindGroupLayout::new_in(context, layout))
     }
     /// Inner layout
     pub fn layout(&self) -> Arc<wgpu::BindGroupLayout> {
@@ -83,6 +129,7 @@ impl BindGroup {
     pub fn cat_layout(&self) -> BindGroupLayout {
         BindGroupLayout {
             layout: self.layout.clone(),
+            context: self.context.clone(),
         }
     }
 }
@@ -2,110 +2,379 @@
 /// see learn wgpu: buffers
 use std::{
     marker::PhantomData,
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
 };
 
 use bytemuck::{Pod, Zeroable};
-use wgpu::{util::DeviceExt, BindingResource, BufferUsages};
+use wgpu::{
+    util::DeviceExt, BindingResource, BufferUsages, VertexAttribute, VertexBufferLayout,
+    VertexFormat, VertexStepMode,
+};
+
+pub use cat_render_derive::{CatUniform, CatVertex};
+
+use super::{context::RenderContext, stats::Live};
+
+/// Struct which is vertex, derive it: `#[derive(CatVertex)]`
+pub trait CatVertex: Pod + Zeroable {
+    const STEP_MODE: VertexStepMode;
+    fn attributes() -> &'static [VertexAttribute];
+    /// For `PipelineOptions` field `buffers`
+    fn desc() -> VertexBufferLayout<'static> {
+        VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: Self::STEP_MODE,
+            attributes: Self::attributes(),
+        }
+    }
+}
 
-use super::UnMutRenderer;
+/// Type of field in `CatVertex`, matrix takes few locations
+pub trait VertexField {
+    const FORMATS: &'static [VertexFormat];
+}
+macro_rules! vertex_field {
+    ($($ty:ty => $($format:ident),+;)*) => {
+        $(impl VertexField for $ty {
+            const FORMATS: &'static [VertexFormat] = &[$(VertexFormat::$format),+];
+        })*
+    };
+}
+vertex_field! {
+    f32 => Float32;
+    [f32; 2] => Float32x2;
+    [f32; 3] => Float32x3;
+    [f32; 4] => Float32x4;
+    u32 => Uint32;
+    [u32; 2] => Uint32x2;
+    [u32; 3] => Uint32x3;
+    [u32; 4] => Uint32x4;
+    i32 => Sint32;
+    [i32; 2] => Sint32x2;
+    [i32; 3] => Sint32x3;
+    [i32; 4] => Sint32x4;
+    [u16; 2] => Uint16x2;
+    [u16; 4] => Uint16x4;
+    [u8; 2] => Uint8x2;
+    [u8; 4] => Uint8x4;
+    [[f32; 2]; 2] => Float32x2, Float32x2;
+    [[f32; 3]; 3] => Float32x3, Float32x3, Float32x3;
+    [[f32; 4]; 4] => Float32x4, Float32x4, Float32x4, Float32x4;
+}
+
+/// Struct with same layout as WGSL uniform, derive it: `#[derive(CatUniform)]`.
+/// Derive fails to compile if padding is missing
+///
+/// ```
+/// # use cat_render::render::buffer::CatUniform;
+/// #[repr(C)]
+/// #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, CatUniform)]
+/// struct Light {
+///     position: [f32; 3],
+///     power: f32,
+///     color: [f32; 3],
+///     _pad: f32,
+///     scale: [f32; 2],
+///     _pad2: [f32; 2],
+/// }
+/// assert_eq!(Light::WGSL_SIZE, 48);
+/// ```
+/// `color` is on offset 16 in WGSL, so padding is needed before it:
+/// ```compile_fail
+/// # use cat_render::render::buffer::CatUniform;
+/// #[repr(C)]
+/// #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, CatUniform)]
+/// struct Light {
+///     power: f32,
+///     color: [f32; 4],
+/// }
+/// ```
+/// Size of struct is rounded up to its align, so padding is needed at end:
+/// ```compile_fail
+/// # use cat_render::render::buffer::CatUniform;
+/// #[repr(C)]
+/// #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, CatUniform)]
+/// struct Light {
+///     color: [f32; 3],
+/// }
+/// ```
+pub trait CatUniform: Pod + Zeroable {
+    const WGSL_ALIGN: usize;
+    const WGSL_SIZE: usize;
+}
+macro_rules! cat_uniform {
+    ($($ty:ty => $align:expr, $size:expr;)*) => {
+        $(impl CatUniform for $ty {
+            const WGSL_ALIGN: usize = $align;
+            const WGSL_SIZE: usize = $size;
+        })*
+    };
+}
+cat_uniform! {
+    f32 => 4, 4;
+    [f32; 2] => 8, 8;
+    [f32; 3] => 16, 12;
+    [f32; 4] => 16, 16;
+    u32 => 4, 4;
+    [u32; 2] => 8, 8;
+    [u32; 3] => 16, 12;
+    [u32; 4] => 16, 16;
+    i32 => 4, 4;
+    [i32; 2] => 8, 8;
+    [i32; 3] => 16, 12;
+    [i32; 4] => 16, 16;
+    [[f32; 2]; 2] => 8, 16;
+}
+/// `array<vec4<f32>, N>`, `mat4x4<f32>` when N is 4
+impl<const N: usize> CatUniform for [[f32; 4]; N] {
+    const WGSL_ALIGN: usize = 16;
+    const WGSL_SIZE: usize = 16 * N;
+}
+
+/// `roundUp(k, n)` from WGSL spec
+#[doc(hidden)]
+pub const fn wgsl_round_up(k: usize, n: usize) -> usize {
+    n.div_ceil(k) * k
+}
+
+/// Shared by clones of buffer, bind groups with buffer are evicted from cache
+/// when last one is dropped
+struct BufferDrop {
+    buffer: wgpu::Buffer,
+    context: RenderContext,
+    _live: Live,
+}
+impl BufferDrop {
+    fn new(buffer: &wgpu::Buffer, context: &RenderContext) -> Arc<Self> {
+        Arc::new(Self {
+            buffer: buffer.clone(),
+            context: context.clone(),
+            _live: Live::buffer(context),
+        })
+    }
+}
+impl Drop for BufferDrop {
+    fn drop(&mut self) {
+        self.context.cache.evict_buffer(&self.buffer);
+    }
+}
 
 /// Buffer with V as Vertex
 #[derive(Clone)]
 pub struct Buffer<V: bytemuck::Pod + bytemuck::Zeroable> {
     pub(crate) wgpu_buffer: wgpu::Buffer,
-    vertices_number: Arc<Mutex<u32>>,
+    vertices_number: Arc<AtomicU32>,
+    context: RenderContext,
+    on_drop: Arc<BufferDrop>,
     mark: PhantomData<V>,
 }
 
 impl<V: Pod + Zeroable> Buffer<V> {
-    /// Creates new buffer
+    /// Creates new buffer with global context
     pub fn new(vertices: Vec<V>, usage: BufferUsages) -> Self {
-        let buffer =
-            UnMutRenderer::get()
-                .device
-                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: Some("Buffer"),
-                    contents: bytemuck::cast_slice(&vertices),
-                    usage,
-                });
+        Self::new_in(&RenderContext::global(), vertices, usage)
+    }
+    /// Creates new buffer on device of `context`
+    pub fn new_in(context: &RenderContext, vertices: Vec<V>, usage: BufferUsages) -> Self {
+        let buffer = context
+            .device()
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Buffer"),
+                contents: bytemuck::cast_slice(&vertices),
+                usage,
+            });
         Self {
+            on_drop: BufferDrop::new(&buffer, context),
             wgpu_buffer: buffer,
-            vertices_number: Arc::new(Mutex::new(vertices.len() as u32)),
+            vertices_number: Arc::new(AtomicU32::new(vertices.len() as u32)),
+            context: context.clone(),
             mark: PhantomData,
         }
     }
     /// Update buffer
     /// PANICS if usage is not BufferUsages::COPY_DST
     pub fn update(&mut self, vertices: Vec<V>) {
-        *self.vertices_number.lock().unwrap() = vertices.len() as u32;
-        UnMutRenderer::get().queue.write_buffer(
-            &self.wgpu_buffer,
-            0,
-            bytemuck::cast_slice(&vertices),
-        );
+        self.vertices_number
+            .store(vertices.len() as u32, Ordering::Relaxed);
+        let bytes: &[u8] = bytemuck::cast_slice(&vertices);
+        self.context.counters.buffer_written(bytes.len());
+        self.context
+            .queue()
+            .write_buffer(&self.wgpu_buffer, 0, bytes);
+    }
+    /// Context on which buffer is created
+    pub fn context(&self) -> &RenderContext {
+        &self.context
     }
     /// Number of vertices
     pub fn get_vertices_number(&self) -> u32 {
-        *self.vertices_number.lock().unwrap()
+        self.vertices_number.load(Ordering::Relaxed)
     }
     /// Need if using it as uniform
-    pub fn as_entire_binding(&self) -> BindingResource {
+    pub fn as_entire_binding(&self) -> BindingResource<'_> {
         self.wgpu_buffer.as_entire_binding()
     }
     pub fn into_untyped(&self) -> UnTypedBuffer {
         UnTypedBuffer {
             wgpu_buffer: self.wgpu_buffer.clone(),
             vertices_number: self.vertices_number.clone(),
+            context: self.context.clone(),
+            _on_drop: self.on_drop.clone(),
         }
     }
 }
+impl<V: CatVertex> Buffer<V> {
+    /// Layout of buffer for `PipelineOptions` field `buffers`
+    pub fn layout() -> VertexBufferLayout<'static> {
+        V::desc()
+    }
+}
+impl<U: CatUniform> Buffer<U> {
+    /// Uniform buffer with one value
+    pub fn new_uniform(value: U) -> Self {
+        Self::new(vec![value], BufferUsages::UNIFORM | BufferUsages::COPY_DST)
+    }
+    /// Write value of uniform buffer
+    pub fn update_uniform(&mut self, value: U) {
+        self.update(vec![value]);
+    }
+}
 /// Buffer with only bytes
 #[derive(Clone)]
 pub struct UnTypedBuffer {
     pub(crate) wgpu_buffer: wgpu::Buffer,
-    pub(crate) vertices_number: Arc<Mutex<u32>>,
+    pub(crate) vertices_number: Arc<AtomicU32>,
+    context: RenderContext,
+    /// Shared with typed buffer
+    _on_drop: Arc<BufferDrop>,
 }
 
 impl UnTypedBuffer {
     /// See Buffer
     pub fn new(vertices_bytes: Vec<Vec<u8>>, usage: BufferUsages) -> Self {
-        let buffer =
-            UnMutRenderer::get()
-                .device
-                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: Some("Buffer"),
-                    contents: &vertices_bytes
-                        .iter()
-                        .flatten()
-                        .cloned()
-                        .collect::<Vec<u8>>(),
-                    usage,
-                });
+        Self::new_in(&RenderContext::global(), vertices_bytes, usage)
+    }
+    /// See Buffer
+    pub fn new_in(
+        context: &RenderContext,
+        vertices_bytes: Vec<Vec<u8>>,
+        usage: BufferUsages,
+    ) -> Self {
+        let buffer = context
+            .device()
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Buffer"),
+                contents: &vertices_bytes
+                    .iter()
+                    .flatten()
+                    .cloned()
+                    .collect::<Vec<u8>>(),
+                usage,
+            });
         Self {
+            _on_drop: BufferDrop::new(&buffer, context),
             wgpu_buffer: buffer,
-            vertices_number: Arc::new(Mutex::new(vertices_bytes.len() as u32)),
+            vertices_number: Arc::new(AtomicU32::new(vertices_bytes.len() as u32)),
+            context: context.clone(),
         }
     }
     /// See Buffer
     pub fn update(&mut self, vertices_bytes: Vec<Vec<u8>>) {
-        *self.vertices_number.lock().unwrap() = vertices_bytes.len() as u32;
-        UnMutRenderer::get().queue.write_buffer(
-            &self.wgpu_buffer,
-            0,
-            &vertices_bytes
-                .iter()
-                .flatten()
-                .cloned()
-                .collect::<Vec<u8>>(),
-        );
+        self.vertices_number
+            .store(vertices_bytes.len() as u32, Ordering::Relaxed);
+        let bytes = vertices_bytes
+            .iter()
+            .flatten()
+            .cloned()
+            .collect::<Vec<u8>>();
+        self.context.counters.buffer_written(bytes.len());
+        self.context
+            .queue()
+            .write_buffer(&self.wgpu_buffer, 0, &bytes);
+    }
+    /// See Buffer
+    pub fn context(&self) -> &RenderContext {
+        &self.context
     }
     /// See Buffer
     pub fn get_vertices_number(&self) -> u32 {
-        *self.vertices_number.lock().unwrap()
+        self.vertices_number.load(Ordering::Relaxed)
     }
     /// See Buffer
-    pub fn as_entire_binding(&self) -> BindingResource {
+    pub fn as_entire_binding(&self) -> BindingResource<'_> {
         self.wgpu_buffer.as_entire_binding()
     }
 }
+
+/// Arguments of one `Render::draw_indirect`
+/// Same layout as `wgpu::util::DrawIndirectArgs`
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, Pod, Zeroable)]
+pub struct DrawIndirectArgs {
+    pub vertex_count: u32,
+    pub instance_count: u32,
+    pub first_vertex: u32,
+    /// Needs `wgpu::Features::INDIRECT_FIRST_INSTANCE` if not 0
+    pub first_instance: u32,
+}
+
+/// Arguments of one `Render::draw_indexed_indirect`
+/// Same layout as `wgpu::util::DrawIndexedIndirectArgs`
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, Pod, Zeroable)]
+pub struct DrawIndexedIndirectArgs {
+    pub index_count: u32,
+    pub instance_count: u32,
+    pub first_index: u32,
+    pub base_vertex: i32,
+    /// Needs `wgpu::Features::INDIRECT_FIRST_INSTANCE` if not 0
+    pub first_instance: u32,
+}
+
+/// Marker for structs which can be in `IndirectBuffer`
+pub trait IndirectArgs: Pod + Zeroable {}
+impl IndirectArgs for DrawIndirectArgs {}
+impl IndirectArgs for DrawIndexedIndirectArgs {}
+
+/// Buffer with draw arguments
+/// Shaders can write into it if created with `BufferUsages::STORAGE`
+#[derive(Clone)]
+pub struct IndirectBuffer<A: IndirectArgs> {
+    buffer: Buffer<A>,
+}
+
+impl<A: IndirectArgs> IndirectBuffer<A> {
+    /// Creates new buffer, `BufferUsages::INDIRECT` is added to usage
+    pub fn new(args: Vec<A>, usage: BufferUsages) -> Self {
+        Self::new_in(&RenderContext::global(), args, usage)
+    }
+    /// See `new`
+    pub fn new_in(context: &RenderContext, args: Vec<A>, usage: BufferUsages) -> Self {
+        Self {
+            buffer: Buffer::new_in(context, args, usage | BufferUsages::INDIRECT),
+        }
+    }
+    /// Update arguments
+    /// PANICS if usage is not BufferUsages::COPY_DST
+    pub fn update(&mut self, args: Vec<A>) {
+        self.buffer.update(args);
+    }
+    /// Number of draws in buffer
+    pub fn get_draws_number(&self) -> u32 {
+        self.buffer.get_vertices_number()
+    }
+    /// Need if shader writes arguments
+    pub fn as_entire_binding(&self) -> BindingResource<'_> {
+        self.buffer.as_entire_binding()
+    }
+    /// Inner buffer
+    pub fn as_buffer(&self) -> &Buffer<A> {
+        &self.buffer
+    }
+    pub(crate) fn offset_of(index: u32) -> wgpu::BufferAddress {
+        index as wgpu::BufferAddress * std::mem::size_of::<A>() as wgpu::BufferAddress
+    }
+}
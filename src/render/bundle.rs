@@ -0,0 +1,246 @@
+//! `RenderBundle` is list of render commands recorded once and replayed with
+//! `Render::execute_bundle`. Good for static backgrounds and level geometry.
+
+use std::{collections::HashMap, ops::Range, sync::Arc};
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::{BufferUsages, DynamicOffset, IndexFormat, RenderPipeline, TextureFormat};
+
+use super::{
+    bind_group::BindGroup,
+    buffer::{Buffer, UnTypedBuffer},
+    context::RenderContext,
+    mesh::MaterialBinding,
+    render_pipeline::PipelineKey,
+    GetIndexFormat, Renderer,
+};
+
+/// Targets which bundle can be used with
+#[derive(Hash, PartialEq, Eq, Clone, Copy, Debug)]
+pub struct BundleTarget {
+    pub format: TextureFormat,
+    pub depth_format: Option<TextureFormat>,
+}
+
+enum BundleCommand {
+    SetPipeline(PipelineKey),
+    SetBindGroup(u32, BindGroup, Vec<DynamicOffset>),
+    /// Bind group of material is taken on encode, it is rebuilt with textures
+    SetMaterial(u32, MaterialBinding),
+    SetVertexBuffer(u32, wgpu::Buffer),
+    SetIndexBuffer(wgpu::Buffer, IndexFormat),
+    Draw(Range<u32>, Range<u32>),
+    DrawIndexed(Range<u32>, i32, Range<u32>),
+}
+
+/// Records commands for `RenderBundle`, same calls as `Render`
+#[derive(Default)]
+pub struct RenderBundleBuilder {
+    commands: Vec<BundleCommand>,
+}
+
+impl RenderBundleBuilder {
+    pub fn new() -> Self {
+        Self {
+            commands: Vec::new(),
+        }
+    }
+    /// Set bind group
+    pub fn set_bind_group(
+        &mut self,
+        index: u32,
+        bind_group: &BindGroup,
+        offsets: &[DynamicOffset],
+    ) {
+        self.commands.push(BundleCommand::SetBindGroup(
+            index,
+            bind_group.clone(),
+            offsets.to_vec(),
+        ));
+    }
+    /// Bind group of material, see `Material::record_me`
+    pub(crate) fn set_material_bind_group(&mut self, index: u32, material: MaterialBinding) {
+        self.commands
+            .push(BundleCommand::SetMaterial(index, material));
+    }
+    /// Set pipeline
+    pub fn set_pipeline(&mut self, key: impl Into<PipelineKey>) {
+        self.commands.push(BundleCommand::SetPipeline(key.into()));
+    }
+    /// draw
+    pub fn draw(&mut self, vertices: Range<u32>, instances: Range<u32>) {
+        self.commands.push(BundleCommand::Draw(vertices, instances));
+    }
+    /// draw with indicies
+    pub fn draw_indexed(&mut self, vertices: Range<u32>, base_vertex: i32, instances: Range<u32>) {
+        self.commands
+            .push(BundleCommand::DrawIndexed(vertices, base_vertex, instances));
+    }
+    /// set vertex buffer
+    pub fn set_vertex_buffer<V: Zeroable + Pod>(&mut self, buffer: &Buffer<V>, slot: u32) {
+        self.set_vertex_buffer_inner(&buffer.wgpu_buffer, slot);
+    }
+    /// set vertex buffer
+    pub fn set_vertex_buffer_untyped(&mut self, buffer: &UnTypedBuffer, slot: u32) {
+        self.set_vertex_buffer_inner(&buffer.wgpu_buffer, slot);
+    }
+    /// set index buffer need for `draw_indexed`
+    pub fn set_index_buffer<V: Zeroable + Pod + GetIndexFormat>(&mut self, buffer: &Buffer<V>) {
+        self.set_index_buffer_untyped_inner(&buffer.wgpu_buffer, V::get_index_format());
+    }
+    /// set index buffer need for `draw_indexed`
+    pub fn set_index_buffer_untyped(&mut self, buffer: &UnTypedBuffer, index_format: IndexFormat) {
+        self.set_index_buffer_untyped_inner(&buffer.wgpu_buffer, index_format);
+    }
+    /// Finish recording
+    pub fn build(self) -> RenderBundle {
+        RenderBundle {
+            commands: self.commands,
+            encoded: HashMap::new(),
+        }
+    }
+
+    fn set_vertex_buffer_inner(&mut self, buffer: &wgpu::Buffer, slot: u32) {
+        if buffer.usage() & BufferUsages::VERTEX == BufferUsages::empty() {
+            log::warn!("Buffer is not for vertex!");
+            log::warn!("Buffer is not added!");
+            return;
+        }
+        self.commands
+            .push(BundleCommand::SetVertexBuffer(slot, buffer.clone()));
+    }
+    fn set_index_buffer_untyped_inner(&mut self, buffer: &wgpu::Buffer, index_format: IndexFormat) {
+        if buffer.usage() & BufferUsages::INDEX == BufferUsages::empty() {
+            log::warn!("Buffer is not for index!");
+            log::warn!("Buffer is not added!");
+            return;
+        }
+        self.commands
+            .push(BundleCommand::SetIndexBuffer(buffer.clone(), index_format));
+    }
+}
+
+struct EncodedBundle {
+    bundle: wgpu::RenderBundle,
+    /// Pipelines which bundle was encoded with
+    pipelines: Vec<Arc<RenderPipeline>>,
+    /// Generations of material bind groups which bundle was encoded with
+    materials: Vec<u64>,
+}
+
+/// Recorded commands, encoded lazily for every target they are executed on.
+/// Rebuilt pipelines and material bind groups (changed or resized textures) are
+/// noticed automatically, other resources are kept as they were recorded.
+pub struct RenderBundle {
+    commands: Vec<BundleCommand>,
+    encoded: HashMap<BundleTarget, EncodedBundle>,
+}
+
+impl RenderBundle {
+    /// Replace recorded commands
+    pub fn record(&mut self, builder: RenderBundleBuilder) {
+        self.commands = builder.commands;
+        self.invalidate();
+    }
+    /// Forget encoded bundles, they will be encoded again on next execute
+    pub fn invalidate(&mut self) {
+        self.encoded.clear();
+    }
+
+    pub(crate) fn get_for_target(
+        &mut self,
+        renderer: &mut Renderer,
+        target: BundleTarget,
+    ) -> &wgpu::RenderBundle {
+        let pipelines = self
+            .commands
+            .iter()
+            .filter_map(|c| match c {
+                BundleCommand::SetPipeline(key) => {
+                    Some(renderer.get_pipeline(target.format, key.clone()))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let (bind_groups, materials): (Vec<_>, Vec<_>) = self
+            .commands
+            .iter()
+            .filter_map(|c| match c {
+                BundleCommand::SetMaterial(_, material) => Some(material.flush()),
+                _ => None,
+            })
+            .unzip();
+        let is_outdated = match self.encoded.get(&target) {
+            Some(encoded) => encoded.pipelines != pipelines || encoded.materials != materials,
+            None => true,
+        };
+        if is_outdated {
+            let bundle = self.encode(renderer.context(), target, &pipelines, &bind_groups);
+            self.encoded.insert(
+                target,
+                EncodedBundle {
+                    bundle,
+                    pipelines,
+                    materials,
+                },
+            );
+        }
+        &self.encoded.get(&target).unwrap().bundle
+    }
+
+    fn encode(
+        &self,
+        context: &RenderContext,
+        target: BundleTarget,
+        pipelines: &[Arc<RenderPipeline>],
+        bind_groups: &[BindGroup],
+    ) -> wgpu::RenderBundle {
+        let mut encoder =
+            context
+                .device()
+                .create_render_bundle_encoder(&wgpu::RenderBundleEncoderDescriptor {
+                    label: Some("Render Bundle Encoder"),
+                    color_formats: &[Some(target.format)],
+                    depth_stencil: target.depth_format.map(|format| {
+                        wgpu::RenderBundleDepthStencil {
+                            format,
+                            depth_read_only: false,
+                            stencil_read_only: true,
+                        }
+                    }),
+                    sample_count: 1,
+                    multiview: None,
+                });
+        let mut pipelines = pipelines.iter();
+        let mut bind_groups = bind_groups.iter();
+        for command in self.commands.iter() {
+            match command {
+                BundleCommand::SetPipeline(_) => {
+                    encoder.set_pipeline(pipelines.next().unwrap());
+                }
+                BundleCommand::SetBindGroup(index, bind_group, offsets) => {
+                    encoder.set_bind_group(*index, bind_group.group.as_ref(), offsets);
+                }
+                BundleCommand::SetMaterial(index, _) => {
+                    let bind_group = bind_groups.next().unwrap();
+                    encoder.set_bind_group(*index, bind_group.group.as_ref(), &[]);
+                }
+                BundleCommand::SetVertexBuffer(slot, buffer) => {
+                    encoder.set_vertex_buffer(*slot, buffer.slice(..));
+                }
+                BundleCommand::SetIndexBuffer(buffer, format) => {
+                    encoder.set_index_buffer(buffer.slice(..), *format);
+                }
+                BundleCommand::Draw(vertices, instances) => {
+                    encoder.draw(vertices.clone(), instances.clone());
+                }
+                BundleCommand::DrawIndexed(indices, base_vertex, instances) => {
+                    encoder.draw_indexed(indices.clone(), *base_vertex, instances.clone());
+                }
+            }
+        }
+        encoder.finish(&wgpu::RenderBundleDescriptor {
+            label: Some("Render Bundle"),
+        })
+    }
+}
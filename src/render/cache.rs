@@ -0,0 +1,260 @@
+//! Samplers and bind groups are created once for same descriptor and resources.
+//! Cache is in `UnMutRenderer`, `Texture` and `BindGroup` use it.
+//! Bind groups are evicted when buffer or texture in them is dropped. Bind groups
+//! and samplers which are used only by cache are removed after `UNUSED_FRAMES`
+//! frames, so switching back to recent one doesn't create it again
+
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    num::NonZeroU64,
+    sync::{Arc, Mutex},
+};
+
+use wgpu::{
+    AddressMode, BindGroupEntry, BindGroupLayout, BindingResource, CompareFunction, Device,
+    FilterMode, SamplerBorderColor, SamplerDescriptor,
+};
+
+/// Entries are checked for eviction when there are this many new ones
+const MIN_SWEEP_SIZE: usize = 64;
+/// Entries used only by cache are kept for this many frames
+const UNUSED_FRAMES: u64 = 60;
+
+#[derive(Default)]
+pub(crate) struct ResourceCache {
+    samplers: Mutex<AgedMap<SamplerKey, wgpu::Sampler>>,
+    bind_groups: Mutex<AgedMap<BindGroupKey, wgpu::BindGroup>>,
+}
+
+/// Map which removes values used only by it and not asked for `UNUSED_FRAMES`
+struct AgedMap<K, V> {
+    /// Value and frame when it was asked last time
+    entries: HashMap<K, (Arc<V>, u64)>,
+    frame: u64,
+    /// Size when next sweep is done before next frame
+    next_sweep: usize,
+}
+
+/// `SamplerDescriptor` without label which can be hashed
+#[derive(PartialEq, Eq, Hash)]
+struct SamplerKey {
+    address_modes: [AddressMode; 3],
+    filters: [FilterMode; 3],
+    /// Bits of f32
+    lod_clamp: [u32; 2],
+    compare: Option<CompareFunction>,
+    anisotropy_clamp: u16,
+    border_color: Option<SamplerBorderColor>,
+}
+impl SamplerKey {
+    fn new(desc: &SamplerDescriptor) -> Self {
+        Self {
+            address_modes: [
+                desc.address_mode_u,
+                desc.address_mode_v,
+                desc.address_mode_w,
+            ],
+            filters: [desc.mag_filter, desc.min_filter, desc.mipmap_filter],
+            lod_clamp: [desc.lod_min_clamp.to_bits(), desc.lod_max_clamp.to_bits()],
+            compare: desc.compare,
+            anisotropy_clamp: desc.anisotropy_clamp,
+            border_color: desc.border_color,
+        }
+    }
+}
+
+/// Layout and resources of bind group. Handles are compared by id
+#[derive(PartialEq, Eq, Hash)]
+struct BindGroupKey {
+    layout: BindGroupLayout,
+    entries: Vec<(u32, ResourceKey)>,
+}
+#[derive(PartialEq, Eq, Hash)]
+enum ResourceKey {
+    Buffer(wgpu::Buffer, u64, Option<NonZeroU64>),
+    TextureView(wgpu::TextureView),
+    Sampler(wgpu::Sampler),
+}
+
+impl ResourceCache {
+    /// Sampler with same descriptor is shared, label is ignored
+    pub(crate) fn sampler(&self, device: &Device, desc: &SamplerDescriptor) -> Arc<wgpu::Sampler> {
+        self.samplers
+            .lock()
+            .unwrap()
+            .get_or_insert_with(SamplerKey::new(desc), || device.create_sampler(desc))
+    }
+    /// Bind group with same layout and resources is shared
+    pub(crate) fn bind_group(
+        &self,
+        device: &Device,
+        layout: &BindGroupLayout,
+        entries: &[BindGroupEntry],
+    ) -> Arc<wgpu::BindGroup> {
+        let create = || {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout,
+                entries,
+            })
+        };
+        let Some(key) = BindGroupKey::new(layout, entries) else {
+            // Arrays of resources are not cached
+            return Arc::new(create());
+        };
+        self.bind_groups
+            .lock()
+            .unwrap()
+            .get_or_insert_with(key, create)
+    }
+    /// Called every frame by `Renderer`, old unused entries are removed
+    pub(crate) fn end_frame(&self) {
+        self.bind_groups.lock().unwrap().end_frame();
+        self.samplers.lock().unwrap().end_frame();
+    }
+    /// Removes bind groups with buffer, called when last handle of buffer is dropped
+    pub(crate) fn evict_buffer(&self, buffer: &wgpu::Buffer) {
+        self.evict(|resource| matches!(resource, ResourceKey::Buffer(b, ..) if b == buffer));
+    }
+    /// Removes bind groups with view, called when texture is dropped or view is replaced
+    pub(crate) fn evict_view(&self, view: &wgpu::TextureView) {
+        self.evict(|resource| matches!(resource, ResourceKey::TextureView(v) if v == view));
+    }
+    fn evict(&self, uses: impl Fn(&ResourceKey) -> bool) {
+        self.bind_groups
+            .lock()
+            .unwrap()
+            .retain_keys(|key| !key.entries.iter().any(|(_, resource)| uses(resource)));
+    }
+    /// Number of cached (samplers, bind groups)
+    pub(crate) fn len(&self) -> (usize, usize) {
+        (
+            self.samplers.lock().unwrap().len(),
+            self.bind_groups.lock().unwrap().len(),
+        )
+    }
+}
+
+impl<K, V> Default for AgedMap<K, V> {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+            frame: 0,
+            next_sweep: MIN_SWEEP_SIZE,
+        }
+    }
+}
+
+impl<K: Hash + Eq, V> AgedMap<K, V> {
+    fn get_or_insert_with(&mut self, key: K, create: impl FnOnce() -> V) -> Arc<V> {
+        let frame = self.frame;
+        let (value, last_used) = self
+            .entries
+            .entry(key)
+            .or_insert_with(|| (Arc::new(create()), frame));
+        *last_used = frame;
+        value.clone()
+    }
+    /// Sweeps every `UNUSED_FRAMES` frames or when map is grown enough
+    fn end_frame(&mut self) {
+        self.frame += 1;
+        if self.frame.is_multiple_of(UNUSED_FRAMES) || self.entries.len() >= self.next_sweep {
+            self.sweep();
+        }
+    }
+    fn sweep(&mut self) {
+        let frame = self.frame;
+        self.entries.retain(|_, (value, last_used)| {
+            Arc::strong_count(value) > 1 || frame - *last_used < UNUSED_FRAMES
+        });
+        self.next_sweep = (self.entries.len() * 2).max(MIN_SWEEP_SIZE);
+    }
+    fn retain_keys(&mut self, keep: impl Fn(&K) -> bool) {
+        self.entries.retain(|key, _| keep(key));
+    }
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+impl BindGroupKey {
+    fn new(layout: &BindGroupLayout, entries: &[BindGroupEntry]) -> Option<Self> {
+        let mut out = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let resource = match &entry.resource {
+                BindingResource::Buffer(b) => {
+                    ResourceKey::Buffer(b.buffer.clone(), b.offset, b.size)
+                }
+                BindingResource::TextureView(view) => ResourceKey::TextureView((*view).clone()),
+                BindingResource::Sampler(sampler) => ResourceKey::Sampler((*sampler).clone()),
+                _ => return None,
+            };
+            out.push((entry.binding, resource));
+        }
+        // Order of entries doesn't change bind group
+        out.sort_by_key(|(binding, _)| *binding);
+        Some(Self {
+            layout: layout.clone(),
+            entries: out,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames<K: Hash + Eq, V>(map: &mut AgedMap<K, V>, count: u64) {
+        for _ in 0..count {
+            map.end_frame();
+        }
+    }
+
+    #[test]
+    fn switching_back_reuses_value() {
+        let mut map = AgedMap::default();
+        let mut created = 0;
+        let mut get = |map: &mut AgedMap<_, _>, key| {
+            map.get_or_insert_with(key, || {
+                created += 1;
+                created
+            })
+        };
+        // Sprite texture A -> B -> A, old bind group is dropped by sprite each time
+        let a = get(&mut map, "a");
+        drop(a);
+        map.end_frame();
+        let b = get(&mut map, "b");
+        drop(b);
+        map.end_frame();
+        let a = get(&mut map, "a");
+        map.end_frame();
+        assert_eq!(*a, 1);
+        assert_eq!(created, 2);
+    }
+
+    #[test]
+    fn unused_values_are_removed() {
+        let mut map = AgedMap::default();
+        let used = map.get_or_insert_with("used", || 0);
+        drop(map.get_or_insert_with("unused", || 1));
+        frames(&mut map, UNUSED_FRAMES - 1);
+        assert_eq!(map.len(), 2);
+        frames(&mut map, UNUSED_FRAMES);
+        assert_eq!(map.len(), 1);
+        assert!(Arc::ptr_eq(&used, &map.get_or_insert_with("used", || 2)));
+    }
+
+    #[test]
+    fn growth_keeps_recent_values() {
+        let mut map = AgedMap::default();
+        frames(&mut map, 1);
+        for i in 0..MIN_SWEEP_SIZE as u32 {
+            drop(map.get_or_insert_with(i, || i));
+        }
+        map.end_frame();
+        assert_eq!(map.len(), MIN_SWEEP_SIZE);
+        assert_eq!(map.next_sweep, MIN_SWEEP_SIZE * 2);
+    }
+}
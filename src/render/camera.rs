@@ -4,6 +4,7 @@ use wgpu::{BufferUsages, ShaderStages};
 use super::{
     bind_group::{BindGroup, BindGroupEntryLayout, BindGroupEntryResources},
     buffer::Buffer,
+    context::RenderContext,
     small::{Rect, Transform},
     surface::SurfaceId,
     texture::Texture,
@@ -55,7 +56,12 @@ impl Camera2D {
             area: self.area.transformed(self.transform),
         }
     }
+    /// Surface of global context
     pub fn new(opt: Camera2DOptions) -> Self {
+        Self::new_in(&RenderContext::global(), opt)
+    }
+    /// Surface of `context`
+    pub fn new_in(context: &RenderContext, opt: Camera2DOptions) -> Self {
         let uniform = CameraUniform { proj: [[0.; 4]; 4] };
         Self {
             transform: opt.transform,
@@ -64,7 +70,8 @@ impl Camera2D {
             viewport_origin: opt.viewport_origin,
             scale: opt.scale,
             surface: opt.surface.clone(),
-            render: CameraRender::new(
+            render: CameraRender::new_in(
+                context,
                 uniform,
                 CameraProjection::P2D {
                     near: opt.near,
@@ -149,10 +156,11 @@ impl Camera2D {
                 self.scale * (projection_width - origin_x),
                 self.scale * (projection_height - origin_y),
             );
-            let uniform = CameraUniform {
-                proj: self.generate_matrix().to_cols_array_2d(),
-            };
-            self.render.buffer.update(vec![uniform]);
+            let view_proj = self.generate_matrix();
+            self.render.view_proj = view_proj;
+            self.render.buffer.update(vec![CameraUniform {
+                proj: view_proj.to_cols_array_2d(),
+            }]);
         }
     }
 }
@@ -181,31 +189,47 @@ pub trait Camera {
     ) -> &CameraRender;
 }
 
+/// Uniform and depth texture of camera, they are on context of surface
 #[derive(Clone)]
 pub struct CameraRender {
     pub buffer: Buffer<CameraUniform>,
     pub bindgroup: BindGroup,
     pub proj: CameraProjection,
+    /// Same matrix as in uniform, for `Render::view_depth`
+    pub view_proj: Mat4,
     pub depth_texture: Option<Texture>,
 }
 impl CameraRender {
+    /// Surface of global context
     pub fn new(
         uniform: CameraUniform,
         proj: CameraProjection,
         surface: SurfaceId,
         depth: bool,
     ) -> Self {
-        let buf = Buffer::<CameraUniform>::new(
+        Self::new_in(&RenderContext::global(), uniform, proj, surface, depth)
+    }
+    /// Surface of `context`
+    pub fn new_in(
+        context: &RenderContext,
+        uniform: CameraUniform,
+        proj: CameraProjection,
+        surface: SurfaceId,
+        depth: bool,
+    ) -> Self {
+        let buf = Buffer::<CameraUniform>::new_in(
+            context,
             vec![uniform],
             BufferUsages::UNIFORM | BufferUsages::COPY_DST,
         );
         let depth_texture = if depth {
-            Some(Texture::create_depth_texture(surface))
+            Some(Texture::create_depth_texture_in(context, surface))
         } else {
             None
         };
         Self {
-            bindgroup: BindGroup::new(
+            bindgroup: BindGroup::new_in(
+                context,
                 vec![BindGroupEntryLayout {
                     binding: 0,
                     visibility: ShaderStages::VERTEX_FRAGMENT,
@@ -222,6 +246,7 @@ impl CameraRender {
             ),
             buffer: buf,
             proj,
+            view_proj: Mat4::from_cols_array_2d(&uniform.proj),
             depth_texture,
         }
     }
@@ -0,0 +1,87 @@
+//! `RenderContext` is device and queue on which resources are created.
+//! Resources keep context they were created on. Functions without context
+//! (`Buffer::new`, `Texture::from_image`, ...) use `RenderContext::global()`
+
+use std::{
+    hash::{Hash, Hasher},
+    ops::Deref,
+    sync::{Arc, MutexGuard},
+};
+
+use wgpu::Features;
+use winit::window::Window;
+
+use super::{
+    surface::{SurfaceId, Surfaces},
+    UnMutRenderer,
+};
+
+/// Handle of device, clones are same device
+#[derive(Clone)]
+pub struct RenderContext {
+    inner: Arc<UnMutRenderer>,
+}
+
+impl RenderContext {
+    /// New instance and device, its resources can't be used with other contexts
+    pub fn new() -> anyhow::Result<Self> {
+        Ok(Self {
+            inner: Arc::new(pollster::block_on(UnMutRenderer::new_async())?),
+        })
+    }
+    /// Context of functions without context, it is other one after
+    /// `Renderer::recover_device`
+    pub fn global() -> Self {
+        Self {
+            inner: UnMutRenderer::get(),
+        }
+    }
+    pub fn device(&self) -> &wgpu::Device {
+        &self.inner.device
+    }
+    pub fn queue(&self) -> &wgpu::Queue {
+        &self.inner.queue
+    }
+    pub fn adapter(&self) -> &wgpu::Adapter {
+        &self.inner.adapter
+    }
+    /// Enabled features of device
+    pub fn features(&self) -> Features {
+        self.inner.features()
+    }
+    /// Device is lost, see `Renderer::recover_device`
+    pub fn is_lost(&self) -> bool {
+        self.inner.is_lost()
+    }
+    pub(crate) fn is_global(&self) -> bool {
+        Arc::ptr_eq(&self.inner, &UnMutRenderer::get())
+    }
+    /// Surfaces which are configured for this device
+    pub(crate) fn surfaces(&self) -> MutexGuard<'_, Surfaces<'static>> {
+        self.inner.surfaces.lock().unwrap()
+    }
+    /// Surface of window on this device
+    pub(crate) fn create_surface(&self, window: Arc<Window>) -> SurfaceId {
+        self.surfaces().create_surface(self, window)
+    }
+}
+
+impl Deref for RenderContext {
+    type Target = UnMutRenderer;
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+/// Same device
+impl PartialEq for RenderContext {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+impl Eq for RenderContext {}
+impl Hash for RenderContext {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.inner).hash(state);
+    }
+}
@@ -0,0 +1,269 @@
+//! Deferred draws: `Render::queue_draw` records them, at end of pass they are
+//! sorted by layer, pipeline and material and drawn without repeated state changes.
+
+use std::{ops::Range, sync::Arc};
+
+use wgpu::{IndexFormat, RenderPipeline};
+
+use super::{bind_group::BindGroup, buffer::UnTypedBuffer, render_pipeline::PipelineKey};
+
+/// Draw call of `DrawItem`
+#[derive(Clone, Debug)]
+pub enum DrawCall {
+    Draw {
+        vertices: Range<u32>,
+        instances: Range<u32>,
+    },
+    DrawIndexed {
+        indices: Range<u32>,
+        base_vertex: i32,
+        instances: Range<u32>,
+    },
+}
+
+/// Draw recorded in queue of `Render`
+#[derive(Clone)]
+pub struct DrawItem {
+    /// Lower layers are drawn first
+    pub layer: i32,
+    /// Some for transparent draws: they are drawn after opaque ones of layer,
+    /// from farthest (bigger depth) to nearest
+    pub depth: Option<f32>,
+    pub pipeline: PipelineKey,
+    /// Slots which are not here use bind groups set on `Render` when item is queued
+    pub bind_groups: Vec<(u32, BindGroup)>,
+    pub vertex_buffers: Vec<(u32, UnTypedBuffer)>,
+    pub index_buffer: Option<(UnTypedBuffer, IndexFormat)>,
+    pub call: DrawCall,
+}
+
+impl DrawItem {
+    pub fn new(pipeline: impl Into<PipelineKey>, call: DrawCall) -> Self {
+        Self {
+            layer: 0,
+            depth: None,
+            pipeline: pipeline.into(),
+            bind_groups: Vec::new(),
+            vertex_buffers: Vec::new(),
+            index_buffer: None,
+            call,
+        }
+    }
+}
+
+/// What is set on render pass, same state is not set again
+#[derive(Default)]
+pub(crate) struct BoundState {
+    pub(crate) pipeline: Option<Arc<RenderPipeline>>,
+    pub(crate) bind_groups: Vec<Option<BindGroup>>,
+    /// Buffer and byte range
+    pub(crate) vertex_buffers: Vec<Option<(wgpu::Buffer, u64, Option<u64>)>>,
+    pub(crate) index_buffer: Option<(wgpu::Buffer, u64, Option<u64>, IndexFormat)>,
+}
+
+impl BoundState {
+    /// Returns true if bind group is changed
+    pub(crate) fn set_bind_group(&mut self, index: u32, bind_group: &BindGroup) -> bool {
+        let index = index as usize;
+        if self.bind_groups.len() <= index {
+            self.bind_groups.resize(index + 1, None);
+        }
+        let slot = &mut self.bind_groups[index];
+        if slot.as_ref().is_some_and(|b| b.group == bind_group.group) {
+            return false;
+        }
+        *slot = Some(bind_group.clone());
+        true
+    }
+    /// Returns true if vertex buffer is changed
+    pub(crate) fn set_vertex_buffer(
+        &mut self,
+        slot: u32,
+        buffer: &wgpu::Buffer,
+        range: (u64, Option<u64>),
+    ) -> bool {
+        let slot = slot as usize;
+        if self.vertex_buffers.len() <= slot {
+            self.vertex_buffers.resize(slot + 1, None);
+        }
+        let new = Some((buffer.clone(), range.0, range.1));
+        if self.vertex_buffers[slot] == new {
+            return false;
+        }
+        self.vertex_buffers[slot] = new;
+        true
+    }
+    /// Returns true if index buffer is changed
+    pub(crate) fn set_index_buffer(
+        &mut self,
+        buffer: &wgpu::Buffer,
+        range: (u64, Option<u64>),
+        format: IndexFormat,
+    ) -> bool {
+        let new = Some((buffer.clone(), range.0, range.1, format));
+        if self.index_buffer == new {
+            return false;
+        }
+        self.index_buffer = new;
+        true
+    }
+}
+
+/// Sorts items and tells pipeline of each item, `pipeline_of` is called once per item
+pub(crate) fn sort_items<P>(
+    items: Vec<DrawItem>,
+    mut pipeline_of: impl FnMut(&PipelineKey) -> Arc<P>,
+) -> Vec<(DrawItem, Arc<P>)> {
+    let mut items = items
+        .into_iter()
+        .map(|item| {
+            let pipeline = pipeline_of(&item.pipeline);
+            (item, pipeline)
+        })
+        .collect::<Vec<_>>();
+    // Stable sort, equal items are in order of queueing
+    items.sort_by(|(a, a_pipeline), (b, b_pipeline)| {
+        a.layer
+            .cmp(&b.layer)
+            .then(a.depth.is_some().cmp(&b.depth.is_some()))
+            .then_with(|| match (a.depth, b.depth) {
+                // Farther first
+                (Some(a), Some(b)) => b.total_cmp(&a),
+                _ => Arc::as_ptr(a_pipeline)
+                    .cmp(&Arc::as_ptr(b_pipeline))
+                    .then_with(|| first_group(a).cmp(&first_group(b)))
+                    .then_with(|| first_buffer(a).cmp(&first_buffer(b))),
+            })
+    });
+    items
+}
+
+/// Material is usually in first bind group
+fn first_group(item: &DrawItem) -> Option<&wgpu::BindGroup> {
+    item.bind_groups
+        .iter()
+        .min_by_key(|(slot, _)| *slot)
+        .map(|(_, b)| b.group.as_ref())
+}
+fn first_buffer(item: &DrawItem) -> Option<&wgpu::Buffer> {
+    item.vertex_buffers
+        .iter()
+        .min_by_key(|(slot, _)| *slot)
+        .map(|(_, b)| &b.wgpu_buffer)
+}
+
+/// (start, end) of byte range
+pub(crate) fn byte_range(range: impl std::ops::RangeBounds<u64>) -> (u64, Option<u64>) {
+    use std::ops::Bound;
+    let start = match range.start_bound() {
+        Bound::Included(s) => *s,
+        Bound::Excluded(s) => s + 1,
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(e) => Some(e + 1),
+        Bound::Excluded(e) => Some(*e),
+        Bound::Unbounded => None,
+    };
+    (start, end)
+}
+
+/// Slice of buffer from `byte_range`
+pub(crate) fn slice(
+    buffer: &wgpu::Buffer,
+    (start, end): (u64, Option<u64>),
+) -> wgpu::BufferSlice<'_> {
+    match end {
+        Some(end) => buffer.slice(start..end),
+        None => buffer.slice(start..),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::render_pipeline::PipelineId;
+
+    /// Item with `tag` as first vertex to find it after sorting
+    fn item(tag: u32, layer: i32, depth: Option<f32>) -> DrawItem {
+        let mut item = DrawItem::new(
+            PipelineId::for_test(0),
+            DrawCall::Draw {
+                vertices: tag..tag + 1,
+                instances: 0..1,
+            },
+        );
+        item.layer = layer;
+        item.depth = depth;
+        item
+    }
+
+    fn tag(item: &DrawItem) -> u32 {
+        match &item.call {
+            DrawCall::Draw { vertices, .. } => vertices.start,
+            DrawCall::DrawIndexed { .. } => unreachable!(),
+        }
+    }
+
+    /// `pipelines[i]` is pipeline of i-th item
+    fn sort(items: Vec<DrawItem>, pipelines: &[Arc<u32>]) -> Vec<(u32, u32)> {
+        let mut pipelines = pipelines.iter();
+        sort_items(items, |_| pipelines.next().unwrap().clone())
+            .iter()
+            .map(|(item, pipeline)| (tag(item), **pipeline))
+            .collect()
+    }
+
+    #[test]
+    fn layers_then_opaque_then_far_to_near() {
+        let p = Arc::new(0);
+        let items = vec![
+            item(0, 1, None),
+            item(1, 0, Some(1.)),
+            item(2, 0, Some(5.)),
+            item(3, 0, None),
+            item(4, -1, Some(0.)),
+            item(5, 0, Some(3.)),
+        ];
+        let sorted = sort(items, &vec![p; 6]);
+        let tags = sorted.iter().map(|(t, _)| *t).collect::<Vec<_>>();
+        assert_eq!(tags, [4, 3, 2, 5, 1, 0]);
+    }
+
+    #[test]
+    fn opaque_grouped_by_pipeline_and_stable() {
+        let a = Arc::new(1);
+        let b = Arc::new(2);
+        let items = (0..6).map(|i| item(i, 0, None)).collect();
+        let pipelines = [
+            a.clone(),
+            b.clone(),
+            a.clone(),
+            b.clone(),
+            a.clone(),
+            b.clone(),
+        ];
+        let sorted = sort(items, &pipelines);
+        let of = |pipeline| {
+            sorted
+                .iter()
+                .filter(|(_, p)| *p == pipeline)
+                .map(|(t, _)| *t)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(of(1), [0, 2, 4]);
+        assert_eq!(of(2), [1, 3, 5]);
+        // Each pipeline is set once
+        let switches = sorted.windows(2).filter(|w| w[0].1 != w[1].1).count();
+        assert_eq!(switches, 1);
+    }
+
+    #[test]
+    fn equal_depth_keeps_queue_order() {
+        let p = Arc::new(0);
+        let items = (0..4).map(|i| item(i, 0, Some(2.))).collect();
+        let sorted = sort(items, &vec![p; 4]);
+        let tags = sorted.iter().map(|(t, _)| *t).collect::<Vec<_>>();
+        assert_eq!(tags, [0, 1, 2, 3]);
+    }
+}
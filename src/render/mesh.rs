@@ -1,16 +1,30 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+    sync::{Arc, Mutex},
+};
+
+use glam::{Mat4, Vec2, Vec3, Vec4};
 
 use bytemuck::{Pod, Zeroable};
-use wgpu::{BindingType, BufferUsages, ShaderStages};
+use wgpu::{
+    BindingType, BufferUsages, SamplerBindingType, ShaderStages, TextureSampleType,
+    TextureViewDimension,
+};
 
 use crate::render::bind_group::BindGroupLayout;
 
 use super::{
     bind_group::{BindGroup, BindGroupEntryLayout, BindGroupEntryResources},
-    buffer::{Buffer, UnTypedBuffer},
-    render_pipeline::{PipelineId, PipelineOptions},
+    buffer::{Buffer, CatUniform, UnTypedBuffer},
+    bundle::RenderBundleBuilder,
+    context::RenderContext,
+    draw_queue::{DrawCall, DrawItem},
+    reflect::{BindingKind, ReflectedBinding, ShaderReflection},
+    render_pipeline::{PipelineId, PipelineKey, PipelineOptions, PipelineState},
+    shader::ShaderError,
     texture::Texture,
-    Render, Renderer,
+    Color, Render, Renderer,
 };
 
 /// Abstraction of buffers for index and verticies need material
@@ -27,23 +41,82 @@ pub struct MeshRef {
 }
 impl MeshRef {
     pub fn draw_with_material(&mut self, render: &mut Render, material: &Material) {
+        self.draw_instanced_with_material(render, material, 0..1);
+    }
+    /// Draw many instances at once.
+    /// Instance buffers must be set by caller (slot 1 and next)
+    pub fn draw_instanced_with_material(
+        &mut self,
+        render: &mut Render,
+        material: &Material,
+        instances: Range<u32>,
+    ) {
         material.use_me(render, 0);
 
         render.set_vertex_buffer_untyped(&self.buffer, 0, ..);
         render.set_index_buffer_untyped(&self.index_buffer, .., wgpu::IndexFormat::Uint16);
-        render.draw_indexed(0..self.index_buffer.get_vertices_number(), 0, 0..1);
+        render.draw_indexed(0..self.index_buffer.get_vertices_number(), 0, instances);
+    }
+    /// Queue draw, see `Render::queue_draw`
+    pub fn queue_with_material(&self, render: &mut Render, material: &Material, layer: i32) {
+        render.queue_draw(self.draw_item(material, layer, None));
+    }
+    /// Queue draw which is sorted back to front by `depth`
+    pub fn queue_transparent_with_material(
+        &self,
+        render: &mut Render,
+        material: &Material,
+        layer: i32,
+        depth: f32,
+    ) {
+        render.queue_draw(self.draw_item(material, layer, Some(depth)));
+    }
+    /// Queue draw with order-independent transparency, see `Render::queue_oit`
+    pub fn queue_oit_with_material(&self, render: &mut Render, material: &Material) {
+        render.queue_oit(self.draw_item(material, 0, None));
+    }
+    /// Record draw into bundle
+    pub fn record_with_material(&self, bundle: &mut RenderBundleBuilder, material: &Material) {
+        material.record_me(bundle, 0);
+        bundle.set_vertex_buffer_untyped(&self.buffer, 0);
+        bundle.set_index_buffer_untyped(&self.index_buffer, wgpu::IndexFormat::Uint16);
+        bundle.draw_indexed(0..self.index_buffer.get_vertices_number(), 0, 0..1);
+    }
+
+    fn draw_item(&self, material: &Material, layer: i32, depth: Option<f32>) -> DrawItem {
+        DrawItem {
+            layer,
+            depth,
+            bind_groups: vec![(0, material.bind_group())],
+            vertex_buffers: vec![(0, self.buffer.clone())],
+            index_buffer: Some((self.index_buffer.clone(), wgpu::IndexFormat::Uint16)),
+            ..DrawItem::new(
+                material.pipeline.clone(),
+                DrawCall::DrawIndexed {
+                    indices: 0..self.index_buffer.get_vertices_number(),
+                    base_vertex: 0,
+                    instances: 0..1,
+                },
+            )
+        }
     }
 }
 
 impl<V: Pod + Zeroable> Mesh<V> {
     /// New buffer
     pub fn new(vertices: Vec<V>, indicies: Vec<u16>) -> Self {
+        Self::new_in(&RenderContext::global(), vertices, indicies)
+    }
+    /// New buffer on device of `context`
+    pub fn new_in(context: &RenderContext, vertices: Vec<V>, indicies: Vec<u16>) -> Self {
         Self {
-            buffer: Buffer::new(
+            buffer: Buffer::new_in(
+                context,
                 vertices.clone(),
                 BufferUsages::VERTEX | BufferUsages::COPY_DST,
             ),
-            index_buffer: Buffer::new(
+            index_buffer: Buffer::new_in(
+                context,
                 indicies.clone(),
                 BufferUsages::INDEX | BufferUsages::COPY_DST,
             ),
@@ -64,6 +137,32 @@ impl<V: Pod + Zeroable> Mesh<V> {
         render.set_index_buffer(&self.index_buffer, ..);
         render.draw_indexed(0..self.index_buffer.get_vertices_number(), 0, 0..1);
     }
+    /// Queue draw, see `Render::queue_draw`
+    pub fn queue_with_material(&self, render: &mut Render, material: &Material, layer: i32) {
+        self.ref_me().queue_with_material(render, material, layer);
+    }
+    /// Queue draw which is sorted back to front by `depth`
+    pub fn queue_transparent_with_material(
+        &self,
+        render: &mut Render,
+        material: &Material,
+        layer: i32,
+        depth: f32,
+    ) {
+        self.ref_me()
+            .queue_transparent_with_material(render, material, layer, depth);
+    }
+    /// Queue draw with order-independent transparency, see `Render::queue_oit`
+    pub fn queue_oit_with_material(&self, render: &mut Render, material: &Material) {
+        self.ref_me().queue_oit_with_material(render, material);
+    }
+    /// Record draw into bundle
+    pub fn record_with_material(&self, bundle: &mut RenderBundleBuilder, material: &Material) {
+        material.record_me(bundle, 0);
+        bundle.set_vertex_buffer(&self.buffer, 0);
+        bundle.set_index_buffer(&self.index_buffer);
+        bundle.draw_indexed(0..self.index_buffer.get_vertices_number(), 0, 0..1);
+    }
     pub fn ref_me(&self) -> MeshRef {
         MeshRef {
             buffer: self.buffer.into_untyped(),
@@ -79,7 +178,14 @@ impl<V: Pod + Zeroable> Mesh<V> {
 pub struct MaterialLayoutBuilder {
     pipeline_options: PipelineOptions,
     uniforms: Vec<(u32, ShaderStages)>,
-    textures: Vec<(u32, u32, ShaderStages)>,
+    textures: Vec<(
+        u32,
+        u32,
+        ShaderStages,
+        TextureViewDimension,
+        TextureSampleType,
+    )>,
+    reflect: bool,
 }
 
 impl MaterialLayoutBuilder {
@@ -89,31 +195,101 @@ impl MaterialLayoutBuilder {
             pipeline_options,
             uniforms: Vec::new(),
             textures: Vec::new(),
+            reflect: false,
+        }
+    }
+    /// Layout is read from group 0 of shader, `register_*` are not needed.
+    /// If `buffers` are empty one vertex buffer is made from vertex inputs.
+    /// Materials can use names of bindings: `Material::from_named`
+    pub fn from_shader(pipeline_options: PipelineOptions) -> Self {
+        Self {
+            reflect: true,
+            ..Self::new(pipeline_options)
         }
     }
     pub fn register_uniform_at(&mut self, slot: u32, vis: ShaderStages) {
         self.uniforms.push((slot, vis));
     }
     pub fn register_texture_at(&mut self, slot: u32, sample_slot: u32, vis: ShaderStages) {
-        self.textures.push((slot, sample_slot, vis));
+        self.register_texture_with_dimension_at(slot, sample_slot, vis, TextureViewDimension::D2);
+    }
+    /// For texture arrays, 3D textures and cubemaps, see `Texture::view_dimension`
+    pub fn register_texture_with_dimension_at(
+        &mut self,
+        slot: u32,
+        sample_slot: u32,
+        vis: ShaderStages,
+        view_dimension: TextureViewDimension,
+    ) {
+        self.register_texture_with_sample_type_at(
+            slot,
+            sample_slot,
+            vis,
+            view_dimension,
+            TextureSampleType::Float { filterable: true },
+        );
+    }
+    /// For formats which can't be filtered (`PixelFormat::sample_type`), sampler is
+    /// `NonFiltering` then. With `from_shader` it replaces reflected texture and sampler
+    pub fn register_texture_with_sample_type_at(
+        &mut self,
+        slot: u32,
+        sample_slot: u32,
+        vis: ShaderStages,
+        view_dimension: TextureViewDimension,
+        sample_type: TextureSampleType,
+    ) {
+        self.textures
+            .push((slot, sample_slot, vis, view_dimension, sample_type));
     }
     // MAYBE TODO: support dynamic offset
-    pub fn build(mut self, renderer: &mut Renderer) -> MaterialLayout {
+    /// Error if shaders are invalid
+    pub fn build(mut self, renderer: &mut Renderer) -> Result<MaterialLayout, ShaderError> {
         let mut entries = Vec::new();
-        for (binding, sample_bind, vis) in self.textures {
+        let reflection = if self.reflect {
+            let mut reflection = renderer.reflect_shader(&mut self.pipeline_options, 0)?;
+            for binding in reflection.bindings.iter_mut() {
+                // Registered textures replace reflected ones
+                if let Some(t) = self.textures.iter().find(|t| t.0 == binding.binding) {
+                    if let BindingKind::Texture { sample_type, .. } = &mut binding.kind {
+                        *sample_type = t.4;
+                    }
+                    continue;
+                }
+                if self.textures.iter().any(|t| t.1 == binding.binding) {
+                    continue;
+                }
+                entries.push(BindGroupEntryLayout {
+                    binding: binding.binding,
+                    visibility: binding.visibility,
+                    ty: binding.binding_type(),
+                });
+            }
+            if self.pipeline_options.buffers.is_empty() && !reflection.vertex_inputs.is_empty() {
+                self.pipeline_options.buffers = vec![reflection.vertex_buffer_layout()];
+            }
+            Some(Arc::new(reflection))
+        } else {
+            None
+        };
+        for (binding, sample_bind, vis, view_dimension, sample_type) in self.textures {
             entries.push(BindGroupEntryLayout {
                 binding,
                 visibility: vis,
                 ty: BindingType::Texture {
                     multisampled: false,
-                    view_dimension: wgpu::TextureViewDimension::D2,
-                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension,
+                    sample_type,
                 },
             });
+            let sampler = match sample_type {
+                TextureSampleType::Float { filterable: true } => SamplerBindingType::Filtering,
+                _ => SamplerBindingType::NonFiltering,
+            };
             entries.push(BindGroupEntryLayout {
                 binding: sample_bind,
                 visibility: vis,
-                ty: BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                ty: BindingType::Sampler(sampler),
             });
         }
         for (binding, vis) in self.uniforms {
@@ -127,16 +303,17 @@ impl MaterialLayoutBuilder {
                 },
             });
         }
-        let bind_group_layout = BindGroupLayout::new(entries);
+        let bind_group_layout = BindGroupLayout::new_in(renderer.context(), entries);
         let mut bgl = vec![bind_group_layout.layout()];
         bgl.append(&mut self.pipeline_options.bind_group_layouts);
         self.pipeline_options.bind_group_layouts = bgl;
-        let pipeline = renderer.create_pipeline(self.pipeline_options);
+        let pipeline = renderer.create_pipeline(self.pipeline_options)?;
 
-        MaterialLayout {
+        Ok(MaterialLayout {
             pipeline,
             bindgroup: bind_group_layout,
-        }
+            reflection,
+        })
     }
 }
 
@@ -145,13 +322,60 @@ impl MaterialLayoutBuilder {
 pub struct MaterialLayout {
     pipeline: PipelineId,
     bindgroup: BindGroupLayout,
+    reflection: Option<Arc<ShaderReflection>>,
+}
+impl MaterialLayout {
+    /// Bindings of shader if layout is made with `MaterialLayoutBuilder::from_shader`
+    pub fn reflection(&self) -> Option<&ShaderReflection> {
+        self.reflection.as_deref()
+    }
 }
 
-/// Material is abstraction for uniforms and textures
-pub struct Material {
-    pipeline: PipelineId,
+/// Values of material parameters, instances read values of base from it
+struct MaterialValues {
+    uniforms: HashMap<u32, Vec<u8>>,
+    /// (view binding, sampler binding, texture)
+    textures: Vec<(u32, Option<u32>, Texture)>,
+    /// Incremented on every change
+    uniforms_version: u64,
+    textures_version: u64,
+}
+
+/// GPU side of material, updated by `Material::flush`
+struct MaterialState {
     bindgroup: BindGroup,
     uniform_buffers: HashMap<u32, UnTypedBuffer>,
+    dirty_uniforms: HashSet<u32>,
+    dirty_textures: bool,
+    /// `Texture::generation` of textures in bind group, it is rebuilt after resize
+    texture_generations: Vec<u64>,
+    /// For instance: bytes of uniforms and textures which are set on instance
+    overridden_uniforms: Vec<(u32, Range<usize>)>,
+    overridden_textures: HashSet<u32>,
+    /// Versions of base values which instance has
+    base_versions: (u64, u64),
+    /// Incremented when bind group is rebuilt
+    generation: u64,
+}
+
+/// Material is abstraction for uniforms and textures.
+/// Changes are kept and uploaded at once on next use or `flush`:
+/// every uniform is written once and bind group is rebuilt once
+pub struct Material {
+    pipeline: PipelineKey,
+    reflection: Option<Arc<ShaderReflection>>,
+    binding: MaterialBinding,
+}
+
+/// Bind group of material, shared with bundles which it is recorded into.
+/// Locks are taken in order `state`, `values`, `base`
+#[derive(Clone)]
+pub(crate) struct MaterialBinding {
+    layout: BindGroupLayout,
+    values: Arc<Mutex<MaterialValues>>,
+    /// Values of base material for instance
+    base: Option<Arc<Mutex<MaterialValues>>>,
+    state: Arc<Mutex<MaterialState>>,
 }
 impl Material {
     /// Uniform is bytes!
@@ -162,75 +386,505 @@ impl Material {
         uniforms: Vec<(u32, Vec<u8>)>,
         textures: Vec<(u32, u32, Texture)>,
     ) -> Self {
-        let mut res = Vec::new();
+        Self::from_bindings(
+            layout,
+            uniforms,
+            textures
+                .into_iter()
+                .map(|(binding, sample_binding, texture)| (binding, Some(sample_binding), texture))
+                .collect(),
+        )
+    }
+    /// Uniforms and textures by names of shader variables, layout must be made
+    /// with `MaterialLayoutBuilder::from_shader`. Every binding must be given,
+    /// samplers are taken from textures. Storage buffers are given as uniforms
+    pub fn from_named(
+        layout: &MaterialLayout,
+        uniforms: Vec<(&str, Vec<u8>)>,
+        textures: Vec<(&str, Texture)>,
+    ) -> anyhow::Result<Self> {
+        let reflection = layout
+            .reflection()
+            .ok_or_else(|| anyhow::anyhow!("Material layout is not made from shader"))?;
+        let mut given = Vec::new();
+        let mut uniform_slots = Vec::new();
+        for (name, bytes) in uniforms {
+            let binding = reflection
+                .binding(name)
+                .ok_or_else(|| anyhow::anyhow!("No binding `{}` in shader", name))?;
+            check_buffer_size(binding, bytes.len())?;
+            given.push(binding.binding);
+            uniform_slots.push((binding.binding, bytes));
+        }
+        let mut texture_slots = Vec::new();
+        for (name, texture) in textures {
+            let binding = reflection
+                .binding(name)
+                .ok_or_else(|| anyhow::anyhow!("No binding `{}` in shader", name))?;
+            check_texture(binding, &texture)?;
+            let sampler = reflection.sampler_for(binding).map(|s| s.binding);
+            given.push(binding.binding);
+            given.extend(sampler);
+            texture_slots.push((binding.binding, sampler, texture));
+        }
+        if let Some(missing) = reflection
+            .bindings
+            .iter()
+            .find(|b| !given.contains(&b.binding))
+        {
+            anyhow::bail!("Binding `{}` is not given", missing.name);
+        }
+        Ok(Self::from_bindings(layout, uniform_slots, texture_slots))
+    }
+    /// New material with own buffers which uses values of this one.
+    /// Parameters set on instance override them, others follow changes of base
+    pub fn instance(&self) -> Material {
+        // Same lock order as in `MaterialBinding::flush`
+        let state = self.binding.state.lock().unwrap();
+        let values = self.binding.values.lock().unwrap();
+        let uniform_buffers = values
+            .uniforms
+            .iter()
+            .map(|(slot, bytes)| {
+                let usage = state.uniform_buffers[slot].wgpu_buffer.usage();
+                let context = self.binding.layout.context();
+                (
+                    *slot,
+                    UnTypedBuffer::new_in(context, vec![bytes.clone()], usage),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+        let bindgroup = create_bind_group(&self.binding.layout, &uniform_buffers, &values.textures);
+        Self {
+            pipeline: self.pipeline.clone(),
+            reflection: self.reflection.clone(),
+            binding: MaterialBinding {
+                layout: self.binding.layout.clone(),
+                values: Arc::new(Mutex::new(MaterialValues {
+                    uniforms: values.uniforms.clone(),
+                    textures: values.textures.clone(),
+                    uniforms_version: 0,
+                    textures_version: 0,
+                })),
+                base: Some(self.binding.values.clone()),
+                state: Arc::new(Mutex::new(MaterialState {
+                    bindgroup,
+                    uniform_buffers,
+                    dirty_uniforms: HashSet::new(),
+                    dirty_textures: false,
+                    texture_generations: texture_generations(&values.textures),
+                    overridden_uniforms: Vec::new(),
+                    overridden_textures: HashSet::new(),
+                    base_versions: (values.uniforms_version, values.textures_version),
+                    generation: 0,
+                })),
+            },
+        }
+    }
+
+    fn from_bindings(
+        layout: &MaterialLayout,
+        uniforms: Vec<(u32, Vec<u8>)>,
+        textures: Vec<(u32, Option<u32>, Texture)>,
+    ) -> Self {
         let mut uniform_buffers: HashMap<u32, UnTypedBuffer> = HashMap::new();
         for (binding, bytes) in uniforms.iter() {
-            let buf = UnTypedBuffer::new(
+            let is_storage = layout
+                .reflection()
+                .and_then(|r| r.bindings.iter().find(|b| b.binding == *binding))
+                .is_some_and(|b| matches!(b.kind, BindingKind::Storage { .. }));
+            let usage = if is_storage {
+                BufferUsages::STORAGE
+            } else {
+                BufferUsages::UNIFORM
+            };
+            let buf = UnTypedBuffer::new_in(
+                layout.bindgroup.context(),
                 vec![bytes.clone()],
-                BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+                usage | BufferUsages::COPY_DST,
             );
             uniform_buffers.insert(*binding, buf);
         }
-
-        for (binding, _) in uniforms.iter() {
-            res.push(BindGroupEntryResources {
-                binding: *binding,
-                resource: uniform_buffers
-                    .get(binding)
-                    .unwrap()
-                    .as_entire_binding()
-                    .clone(),
-            });
-        }
-        for (binding, sample_binding, texture) in textures.iter() {
-            res.push(BindGroupEntryResources {
-                binding: *binding,
-                resource: wgpu::BindingResource::TextureView(&texture.view),
-            });
-            res.push(BindGroupEntryResources {
-                binding: *sample_binding,
-                resource: wgpu::BindingResource::Sampler(&texture.sampler),
-            });
-        }
-        let bind_group = BindGroup::new_from_layout(res, &layout.bindgroup);
+        let bindgroup = create_bind_group(&layout.bindgroup, &uniform_buffers, &textures);
+        let generations = texture_generations(&textures);
         Self {
-            bindgroup: bind_group,
-            pipeline: layout.pipeline.clone(),
-            uniform_buffers,
+            pipeline: layout.pipeline.clone().into(),
+            reflection: layout.reflection.clone(),
+            binding: MaterialBinding {
+                layout: layout.bindgroup.clone(),
+                values: Arc::new(Mutex::new(MaterialValues {
+                    uniforms: uniforms.into_iter().collect(),
+                    textures,
+                    uniforms_version: 0,
+                    textures_version: 0,
+                })),
+                base: None,
+                state: Arc::new(Mutex::new(MaterialState {
+                    bindgroup,
+                    uniform_buffers,
+                    dirty_uniforms: HashSet::new(),
+                    dirty_textures: false,
+                    texture_generations: generations,
+                    overridden_uniforms: Vec::new(),
+                    overridden_textures: HashSet::new(),
+                    base_versions: (0, 0),
+                    generation: 0,
+                })),
+            },
         }
     }
+    /// Render material with other blend, depth and etc.
+    /// Shaders are not compiled again
+    pub fn set_pipeline_state(&mut self, state: PipelineState) {
+        self.pipeline.state = state;
+    }
     /// Update uniform
     pub fn update_uniform(&mut self, slot: u32, bytes: Vec<u8>) {
-        self.uniform_buffers
+        let len = bytes.len();
+        let mut values = self.binding.values.lock().unwrap();
+        *values.uniforms.get_mut(&slot).expect("No uniform on slot!") = bytes;
+        values.uniforms_version += 1;
+        drop(values);
+        self.mark_uniform(slot, 0..len);
+    }
+    /// Update uniform with struct from `#[derive(CatUniform)]`
+    pub fn update_uniform_typed<U: CatUniform>(&mut self, slot: u32, value: &U) {
+        self.update_uniform(slot, bytemuck::bytes_of(value).to_vec());
+    }
+    /// Update uniform by name of shader variable, size is checked
+    pub fn update_uniform_named(&mut self, name: &str, bytes: Vec<u8>) -> anyhow::Result<()> {
+        let binding = self
+            .reflection
+            .as_ref()
+            .and_then(|r| r.binding(name))
+            .ok_or_else(|| anyhow::anyhow!("No binding `{}` in shader", name))?;
+        check_buffer_size(binding, bytes.len())?;
+        let slot = binding.binding;
+        self.update_uniform(slot, bytes);
+        Ok(())
+    }
+    /// Set uniform or its field by name: `uni`, `uni.color` or `color`
+    /// if only one uniform has such field. Size must be same as in shader
+    pub fn set_bytes(&mut self, name: &str, bytes: &[u8]) -> anyhow::Result<()> {
+        let (slot, offset, size) = self.find_parameter(name)?;
+        if bytes.len() != size {
+            anyhow::bail!("`{}` is {} bytes, given {}", name, size, bytes.len());
+        }
+        let mut values = self.binding.values.lock().unwrap();
+        let data = values
+            .uniforms
             .get_mut(&slot)
-            .expect("No uniform on slot!")
-            .update(vec![bytes]);
+            .ok_or_else(|| anyhow::anyhow!("Uniform `{}` is not given", name))?;
+        if data.len() < offset + size {
+            anyhow::bail!("Uniform `{}` is smaller than in shader", name);
+        }
+        data[offset..offset + size].copy_from_slice(bytes);
+        values.uniforms_version += 1;
+        drop(values);
+        self.mark_uniform(slot, offset..offset + size);
+        Ok(())
+    }
+    pub fn set_f32(&mut self, name: &str, value: f32) -> anyhow::Result<()> {
+        self.set_bytes(name, bytemuck::bytes_of(&value))
+    }
+    pub fn set_u32(&mut self, name: &str, value: u32) -> anyhow::Result<()> {
+        self.set_bytes(name, bytemuck::bytes_of(&value))
+    }
+    pub fn set_i32(&mut self, name: &str, value: i32) -> anyhow::Result<()> {
+        self.set_bytes(name, bytemuck::bytes_of(&value))
+    }
+    pub fn set_vec2(&mut self, name: &str, value: Vec2) -> anyhow::Result<()> {
+        self.set_bytes(name, bytemuck::bytes_of(&value.to_array()))
+    }
+    pub fn set_vec3(&mut self, name: &str, value: Vec3) -> anyhow::Result<()> {
+        self.set_bytes(name, bytemuck::bytes_of(&value.to_array()))
+    }
+    pub fn set_vec4(&mut self, name: &str, value: Vec4) -> anyhow::Result<()> {
+        self.set_bytes(name, bytemuck::bytes_of(&value.to_array()))
+    }
+    pub fn set_mat4(&mut self, name: &str, value: Mat4) -> anyhow::Result<()> {
+        self.set_bytes(name, bytemuck::bytes_of(&value.to_cols_array()))
+    }
+    /// Color as `vec4<f32>`
+    pub fn set_color(&mut self, name: &str, value: Color) -> anyhow::Result<()> {
+        self.set_bytes(name, bytemuck::bytes_of(&value.to_array()))
+    }
+    /// Struct from `#[derive(CatUniform)]`
+    pub fn set_uniform<U: CatUniform>(&mut self, name: &str, value: &U) -> anyhow::Result<()> {
+        self.set_bytes(name, bytemuck::bytes_of(value))
+    }
+    /// Set texture by name of shader variable, its sampler is set too
+    pub fn set_texture(&mut self, name: &str, texture: Texture) -> anyhow::Result<()> {
+        let binding = self
+            .reflection
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Material layout is not made from shader"))?
+            .binding(name)
+            .ok_or_else(|| anyhow::anyhow!("No binding `{}` in shader", name))?;
+        check_texture(binding, &texture)?;
+        let slot = binding.binding;
+        let mut values = self.binding.values.lock().unwrap();
+        let entry = values
+            .textures
+            .iter_mut()
+            .find(|(b, _, _)| *b == slot)
+            .ok_or_else(|| anyhow::anyhow!("Texture `{}` is not given", name))?;
+        entry.2 = texture;
+        values.textures_version += 1;
+        drop(values);
+        let mut state = self.binding.state.lock().unwrap();
+        state.dirty_textures = true;
+        if self.binding.base.is_some() {
+            state.overridden_textures.insert(slot);
+        }
+        Ok(())
     }
     /// Globaly change textures
     pub fn change_textures(&mut self, textures: Vec<(u32, u32, Texture)>) {
-        let mut res = Vec::new();
+        // Same lock order as in `MaterialBinding::flush`
+        let mut state = self.binding.state.lock().unwrap();
+        let mut values = self.binding.values.lock().unwrap();
+        values.textures = textures
+            .into_iter()
+            .map(|(binding, sample_binding, texture)| (binding, Some(sample_binding), texture))
+            .collect();
+        values.textures_version += 1;
+        state.dirty_textures = true;
+        if self.binding.base.is_some() {
+            state
+                .overridden_textures
+                .extend(values.textures.iter().map(|(b, _, _)| *b));
+        }
+    }
+    /// Upload changed parameters now, it is done on next use
+    /// and on execute of bundle with material too
+    pub fn flush(&self) {
+        self.flush_inner();
+    }
+    /// Need for render
+    pub fn use_me(&self, render: &mut Render, slot: u32) {
+        let bindgroup = self.flush_inner();
+        render.set_pipeline(self.pipeline.clone());
+        render.set_bind_group(slot, &bindgroup, &[]);
+    }
+    /// Same as `use_me` but for bundle
+    pub fn record_me(&self, bundle: &mut RenderBundleBuilder, slot: u32) {
+        bundle.set_pipeline(self.pipeline.clone());
+        bundle.set_material_bind_group(slot, self.binding.clone());
+    }
 
-        for (binding, buffer) in self.uniform_buffers.iter() {
-            res.push(BindGroupEntryResources {
-                binding: *binding,
-                resource: buffer.as_entire_binding(),
-            });
+    /// Bind group with all changes uploaded
+    pub(crate) fn bind_group(&self) -> BindGroup {
+        self.flush_inner()
+    }
+
+    /// (binding, offset, size) of parameter
+    fn find_parameter(&self, name: &str) -> anyhow::Result<(u32, usize, usize)> {
+        let reflection = self
+            .reflection
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Material layout is not made from shader"))?;
+        let buffer_size = |kind: BindingKind| match kind {
+            BindingKind::Uniform { size } | BindingKind::Storage { size, .. } => Some(size),
+            _ => None,
+        };
+        if let Some(binding) = reflection.binding(name) {
+            let size = buffer_size(binding.kind)
+                .ok_or_else(|| anyhow::anyhow!("Binding `{}` is not buffer", name))?;
+            return Ok((binding.binding, 0, size as usize));
         }
-        for (binding, sample_binding, texture) in textures.iter() {
-            res.push(BindGroupEntryResources {
-                binding: *binding,
-                resource: wgpu::BindingResource::TextureView(&texture.view),
-            });
+        let mut found = reflection.bindings.iter().flat_map(|b| {
+            b.members
+                .iter()
+                .filter(|m| match name.split_once('.') {
+                    Some((binding, member)) => b.name == binding && m.name == member,
+                    None => m.name == name,
+                })
+                .map(|m| (b.binding, m.offset as usize, m.size as usize))
+        });
+        match (found.next(), found.next()) {
+            (Some(parameter), None) => Ok(parameter),
+            (Some(_), Some(_)) => {
+                anyhow::bail!("`{}` is in few uniforms, use `uniform.{}`", name, name)
+            }
+            (None, _) => anyhow::bail!("No parameter `{}` in shader", name),
+        }
+    }
+    fn mark_uniform(&self, slot: u32, range: Range<usize>) {
+        let mut state = self.binding.state.lock().unwrap();
+        state.dirty_uniforms.insert(slot);
+        if self.binding.base.is_some()
+            && !state.overridden_uniforms.contains(&(slot, range.clone()))
+        {
+            state.overridden_uniforms.push((slot, range));
+        }
+    }
+    fn flush_inner(&self) -> BindGroup {
+        self.binding.flush().0
+    }
+}
+
+impl MaterialBinding {
+    /// Applies changes of base and own changes, returns bind group to use
+    /// and its generation
+    pub(crate) fn flush(&self) -> (BindGroup, u64) {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        let mut values = self.values.lock().unwrap();
+        if let Some(base) = &self.base {
+            let base = base.lock().unwrap();
+            if base.uniforms_version != state.base_versions.0 {
+                for (slot, bytes) in base.uniforms.iter() {
+                    let mut bytes = bytes.clone();
+                    if let Some(own) = values.uniforms.get(slot) {
+                        for (_, range) in
+                            state.overridden_uniforms.iter().filter(|(s, _)| s == slot)
+                        {
+                            if range.end <= bytes.len() && range.end <= own.len() {
+                                bytes[range.clone()].copy_from_slice(&own[range.clone()]);
+                            }
+                        }
+                    }
+                    values.uniforms.insert(*slot, bytes);
+                    state.dirty_uniforms.insert(*slot);
+                }
+                values.uniforms_version += 1;
+                state.base_versions.0 = base.uniforms_version;
+            }
+            if base.textures_version != state.base_versions.1 {
+                for (slot, sample_slot, texture) in base.textures.iter() {
+                    if state.overridden_textures.contains(slot) {
+                        continue;
+                    }
+                    match values.textures.iter_mut().find(|(s, _, _)| s == slot) {
+                        Some(entry) => *entry = (*slot, *sample_slot, texture.clone()),
+                        None => values.textures.push((*slot, *sample_slot, texture.clone())),
+                    }
+                }
+                values.textures_version += 1;
+                state.dirty_textures = true;
+                state.base_versions.1 = base.textures_version;
+            }
+        }
+        for slot in state.dirty_uniforms.drain() {
+            if let (Some(buffer), Some(bytes)) = (
+                state.uniform_buffers.get_mut(&slot),
+                values.uniforms.get(&slot),
+            ) {
+                buffer.update(vec![bytes.clone()]);
+            }
+        }
+        if !state.dirty_textures
+            && values
+                .textures
+                .iter()
+                .map(|(_, _, texture)| texture.generation())
+                .ne(state.texture_generations.iter().copied())
+        {
+            state.dirty_textures = true;
+        }
+        if state.dirty_textures {
+            state.bindgroup =
+                create_bind_group(&self.layout, &state.uniform_buffers, &values.textures);
+            state.texture_generations = texture_generations(&values.textures);
+            state.dirty_textures = false;
+            state.generation += 1;
+        }
+        (state.bindgroup.clone(), state.generation)
+    }
+}
+
+fn texture_generations(textures: &[(u32, Option<u32>, Texture)]) -> Vec<u64> {
+    textures
+        .iter()
+        .map(|(_, _, texture)| texture.generation())
+        .collect()
+}
+
+fn create_bind_group(
+    layout: &BindGroupLayout,
+    uniform_buffers: &HashMap<u32, UnTypedBuffer>,
+    textures: &[(u32, Option<u32>, Texture)],
+) -> BindGroup {
+    let mut res = Vec::new();
+    for (binding, buffer) in uniform_buffers.iter() {
+        res.push(BindGroupEntryResources {
+            binding: *binding,
+            resource: buffer.as_entire_binding(),
+        });
+    }
+    let views = textures
+        .iter()
+        .map(|(_, _, texture)| (texture.view(), texture.sampler()))
+        .collect::<Vec<_>>();
+    for ((binding, sample_binding, _), (view, sampler)) in textures.iter().zip(views.iter()) {
+        res.push(BindGroupEntryResources {
+            binding: *binding,
+            resource: wgpu::BindingResource::TextureView(view),
+        });
+        if let Some(sample_binding) = sample_binding {
             res.push(BindGroupEntryResources {
                 binding: *sample_binding,
-                resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                resource: wgpu::BindingResource::Sampler(sampler),
             });
         }
-        self.bindgroup = BindGroup::new_from_layout(res, &self.bindgroup.cat_layout());
     }
-    /// Need for render
-    pub fn use_me(&self, render: &mut Render, slot: u32) {
-        render.set_pipeline(self.pipeline.clone());
-        render.set_bind_group(slot, &self.bindgroup, &[]);
+    BindGroup::new_from_layout(res, layout)
+}
+
+fn check_texture(binding: &ReflectedBinding, texture: &Texture) -> anyhow::Result<()> {
+    match binding.kind {
+        BindingKind::Texture { view_dimension, .. }
+            if view_dimension != texture.view_dimension() =>
+        {
+            anyhow::bail!(
+                "Texture `{}` is {:?} in shader, given {:?}",
+                binding.name,
+                view_dimension,
+                texture.view_dimension()
+            )
+        }
+        BindingKind::Texture { sample_type, .. }
+            if !is_sample_type_compatible(sample_type, texture.format()) =>
+        {
+            anyhow::bail!(
+                "Texture `{}` is {:?} in shader, given {:?}",
+                binding.name,
+                sample_type,
+                texture.format()
+            )
+        }
+        BindingKind::Texture { .. } => Ok(()),
+        _ => anyhow::bail!("Binding `{}` is not texture", binding.name),
+    }
+}
+
+/// Not filterable binding accepts any float texture
+fn is_sample_type_compatible(sample_type: TextureSampleType, format: wgpu::TextureFormat) -> bool {
+    match (sample_type, format.sample_type(None, None)) {
+        (TextureSampleType::Float { filterable: false }, Some(TextureSampleType::Float { .. })) => {
+            true
+        }
+        (sample_type, Some(format)) => sample_type == format,
+        (_, None) => false,
+    }
+}
+
+fn check_buffer_size(binding: &ReflectedBinding, len: usize) -> anyhow::Result<()> {
+    match binding.kind {
+        BindingKind::Uniform { size } if len != size as usize => anyhow::bail!(
+            "Uniform `{}` is {} bytes, given {}",
+            binding.name,
+            size,
+            len
+        ),
+        BindingKind::Storage { size, .. } if len < size as usize => anyhow::bail!(
+            "Storage `{}` is at least {} bytes, given {}",
+            binding.name,
+            size,
+            len
+        ),
+        BindingKind::Uniform { .. } | BindingKind::Storage { .. } => Ok(()),
+        _ => anyhow::bail!("Binding `{}` is not buffer", binding.name),
     }
 }
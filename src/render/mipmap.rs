@@ -0,0 +1,221 @@
+//! Mip chain of textures. On GPU every level is drawn from previous one with
+//! linear sampler, formats which can't be filtered are downsampled on CPU from image
+//! (see `Texture::from_image_with_options`)
+
+use std::{collections::HashMap, sync::Mutex};
+
+use glam::UVec2;
+use wgpu::{Device, Queue, TextureFormat};
+
+use super::texture::PixelFormat;
+
+const BLIT_SHADER: &str = "@group(0) @binding(0)
+var source: texture_2d<f32>;
+@group(0) @binding(1)
+var source_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2<f32>(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(source, source_sampler, in.uv);
+}
+";
+
+/// Number of levels down to 1x1
+pub(crate) fn mip_level_count(size: UVec2) -> u32 {
+    size.max_element().max(1).ilog2() + 1
+}
+
+/// Levels of format can be made on GPU
+pub(crate) fn is_gpu_supported(format: TextureFormat) -> bool {
+    PixelFormat::from_wgpu(format).is_some_and(|format| format.is_filterable())
+}
+
+/// Pipelines for every format, created on first use. It is in `UnMutRenderer`
+#[derive(Default)]
+pub(crate) struct MipmapGenerator {
+    blit: Mutex<Option<Blit>>,
+}
+
+struct Blit {
+    shader: wgpu::ShaderModule,
+    layout: wgpu::BindGroupLayout,
+    pipeline_layout: wgpu::PipelineLayout,
+    sampler: wgpu::Sampler,
+    pipelines: HashMap<TextureFormat, wgpu::RenderPipeline>,
+}
+
+impl Blit {
+    fn new(device: &Device) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Mipmap shader"),
+            source: wgpu::ShaderSource::Wgsl(BLIT_SHADER.into()),
+        });
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Mipmap layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Mipmap pipeline layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Mipmap sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        Self {
+            shader,
+            layout,
+            pipeline_layout,
+            sampler,
+            pipelines: HashMap::new(),
+        }
+    }
+    fn pipeline(&mut self, device: &Device, format: TextureFormat) -> wgpu::RenderPipeline {
+        self.pipelines
+            .entry(format)
+            .or_insert_with(|| {
+                device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("Mipmap pipeline"),
+                    layout: Some(&self.pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &self.shader,
+                        entry_point: Some("vs_main"),
+                        compilation_options: Default::default(),
+                        buffers: &[],
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &self.shader,
+                        entry_point: Some("fs_main"),
+                        compilation_options: Default::default(),
+                        targets: &[Some(format.into())],
+                    }),
+                    primitive: wgpu::PrimitiveState::default(),
+                    depth_stencil: None,
+                    multisample: wgpu::MultisampleState::default(),
+                    multiview: None,
+                    cache: None,
+                })
+            })
+            .clone()
+    }
+}
+
+impl MipmapGenerator {
+    /// Draws levels 1.. from level 0 for every layer. Texture needs `RENDER_ATTACHMENT` and
+    /// `TEXTURE_BINDING` usage, format from `is_gpu_supported` and 2D dimension
+    pub(crate) fn generate(&self, device: &Device, queue: &Queue, texture: &wgpu::Texture) {
+        let mut blit = self.blit.lock().unwrap();
+        let blit = blit.get_or_insert_with(|| Blit::new(device));
+        let pipeline = blit.pipeline(device, texture.format());
+        let level_view = |level: u32, layer: u32| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_mip_level: level,
+                mip_level_count: Some(1),
+                base_array_layer: layer,
+                array_layer_count: Some(1),
+                ..Default::default()
+            })
+        };
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Mipmap encoder"),
+        });
+        let levels = (0..texture.depth_or_array_layers())
+            .flat_map(|layer| (1..texture.mip_level_count()).map(move |level| (layer, level)));
+        for (layer, level) in levels {
+            let source = level_view(level - 1, layer);
+            let target = level_view(level, layer);
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &blit.layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&source),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&blit.sampler),
+                    },
+                ],
+            });
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Mipmap pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+        queue.submit([encoder.finish()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn level_count() {
+        assert_eq!(mip_level_count(UVec2::new(0, 0)), 1);
+        assert_eq!(mip_level_count(UVec2::new(1, 1)), 1);
+        assert_eq!(mip_level_count(UVec2::new(2, 1)), 2);
+        assert_eq!(mip_level_count(UVec2::new(3, 3)), 2);
+        assert_eq!(mip_level_count(UVec2::new(256, 256)), 9);
+        assert_eq!(mip_level_count(UVec2::new(300, 10)), 9);
+        assert_eq!(mip_level_count(UVec2::new(1, 1024)), 11);
+    }
+
+    #[test]
+    fn gpu_formats() {
+        assert!(is_gpu_supported(TextureFormat::Rgba8UnormSrgb));
+        assert!(is_gpu_supported(TextureFormat::Rgba16Float));
+        assert!(!is_gpu_supported(TextureFormat::Rgba32Float));
+        assert!(!is_gpu_supported(TextureFormat::R32Uint));
+        assert!(!is_gpu_supported(TextureFormat::Bgra8Unorm));
+    }
+}
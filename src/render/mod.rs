@@ -1,54 +1,94 @@
 pub use bytemuck;
 use camera::{Camera, CameraProjection, CameraRender};
+use draw_queue::{BoundState, DrawCall, DrawItem};
 pub use wgpu;
 
 pub mod bind_group;
 pub mod buffer;
+pub mod bundle;
+mod cache;
 pub mod camera;
+pub mod context;
+pub mod draw_queue;
 pub mod mesh;
+mod mipmap;
+mod oit;
+pub mod parallel;
+pub mod reflect;
+pub mod registry;
 pub mod render_pipeline;
+pub mod shader;
 pub mod small;
+pub mod stats;
 pub mod surface;
 pub mod texture;
 
+pub use context::RenderContext;
 pub use small::Color;
 
 use bind_group::BindGroup;
 use bind_group::{BindGroupEntryLayout, BindGroupEntryResources};
-use buffer::{Buffer, UnTypedBuffer};
+use buffer::{
+    Buffer, DrawIndexedIndirectArgs, DrawIndirectArgs, IndirectArgs, IndirectBuffer, UnTypedBuffer,
+};
+use bundle::{BundleTarget, RenderBundle};
+use cache::ResourceCache;
+use glam::{UVec2, Vec3};
 use image::DynamicImage;
-use render_pipeline::{PipelineId, PipelineOptions, Pipelines};
+use mipmap::MipmapGenerator;
+use oit::Oit;
+use reflect::ShaderReflection;
+use registry::{Registered, ResourceRegistry};
+use render_pipeline::{PipelineId, PipelineKey, PipelineOptions, Pipelines};
+use shader::ShaderError;
+use stats::{Counters, GpuProfiler, PassQueries, RenderStats};
 use surface::{SurfaceId, Surfaces};
-use texture::Texture;
+use texture::{Texture, TextureOptions};
 
-use std::sync::LazyLock;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    LazyLock, Mutex, RwLock,
+};
 use std::{
     ops::{Range, RangeBounds},
-    rc::Rc,
     sync::Arc,
 };
 
 use bytemuck::{Pod, Zeroable};
 use wgpu::{
-    Adapter, BufferUsages, Device, DynamicOffset, FilterMode, IndexFormat, Instance, Queue,
-    RenderPass, RenderPipeline, Surface, SurfaceTexture, TextureFormat, TextureView,
-};
-use winit::{
-    dpi::PhysicalSize,
-    window::{Window, WindowId},
+    Adapter, AddressMode, BufferUsages, Device, DynamicOffset, Features, FilterMode, IndexFormat,
+    Instance, Queue, RenderPass, RenderPipeline, Surface, SurfaceTexture, TextureFormat,
+    TextureUsages, TextureView,
 };
+use winit::{dpi::PhysicalSize, window::WindowId};
+
+/// Features which are enabled if adapter supports them
+pub const OPTIONAL_FEATURES: Features = Features::MULTI_DRAW_INDIRECT
+    .union(Features::INDIRECT_FIRST_INSTANCE)
+    .union(Features::PIPELINE_CACHE)
+    .union(Features::TIMESTAMP_QUERY)
+    .union(Features::PIPELINE_STATISTICS_QUERY)
+    .union(Features::ADDRESS_MODE_CLAMP_TO_BORDER);
 
 pub struct UnMutRenderer {
     pub(crate) instance: Instance,
     pub(crate) device: Device,
     pub(crate) queue: Queue,
     pub(crate) adapter: Adapter,
+    pub(crate) cache: ResourceCache,
+    pub(crate) mipmaps: MipmapGenerator,
+    /// Stats of resources of this device
+    pub(crate) counters: Arc<Counters>,
+    /// Surfaces configured for this device, moved to new one by `Renderer::recover_device`
+    pub(crate) surfaces: Mutex<Surfaces<'static>>,
+    /// Set by device lost callback
+    lost: Arc<AtomicBool>,
 }
 impl UnMutRenderer {
     fn new() -> Self {
-        pollster::block_on(Self::new_async())
+        pollster::block_on(Self::new_async()).expect("Failed to create device")
     }
-    async fn new_async() -> Self {
+    async fn new_async() -> anyhow::Result<Self> {
         let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
             #[cfg(not(target_arch = "wasm32"))]
             backends: wgpu::Backends::DX12,
@@ -63,11 +103,11 @@ impl UnMutRenderer {
                 force_fallback_adapter: false,
             })
             .await
-            .unwrap();
+            .ok_or_else(|| anyhow::anyhow!("No suitable adapter"))?;
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
-                    required_features: wgpu::Features::empty(),
+                    required_features: adapter.features() & OPTIONAL_FEATURES,
                     // WebGL doesn't support all of wgpu's features, so if
                     // we're building for the web, we'll have to disable some.
                     required_limits: if cfg!(target_arch = "wasm32") {
@@ -80,58 +120,263 @@ impl UnMutRenderer {
                 },
                 None, // Trace path
             )
-            .await
-            .unwrap();
-        Self {
+            .await?;
+        let lost = Arc::new(AtomicBool::new(false));
+        let lost_flag = lost.clone();
+        device.set_device_lost_callback(move |reason, message| {
+            log::error!("Device is lost ({:?}): {}", reason, message);
+            lost_flag.store(true, Ordering::Release);
+        });
+        Ok(Self {
             instance,
             device,
             queue,
             adapter,
-        }
+            cache: ResourceCache::default(),
+            mipmaps: MipmapGenerator::default(),
+            counters: Arc::default(),
+            surfaces: Mutex::new(Surfaces::new()),
+            lost,
+        })
     }
+    /// Current global renderer, after `Renderer::recover_device` it is other one.
+    /// Use `RenderContext` instead
     pub fn get() -> Arc<UnMutRenderer> {
-        UN_MUT_RENDERER.clone()
+        UN_MUT_RENDERER.read().unwrap().clone()
+    }
+    /// Creates instance, adapter and device again, old ones are dropped
+    /// when last `Arc` of them is dropped
+    fn recreate() -> anyhow::Result<()> {
+        let renderer = pollster::block_on(Self::new_async())?;
+        *UN_MUT_RENDERER.write().unwrap() = Arc::new(renderer);
+        Ok(())
+    }
+    /// Device is lost, see `Renderer::recover_device`
+    pub fn is_lost(&self) -> bool {
+        self.lost.load(Ordering::Acquire)
+    }
+    /// Enabled features of device
+    pub fn features(&self) -> Features {
+        self.device.features()
     }
 }
-static UN_MUT_RENDERER: LazyLock<Arc<UnMutRenderer>> =
-    LazyLock::new(|| Arc::new(UnMutRenderer::new()));
+static UN_MUT_RENDERER: LazyLock<RwLock<Arc<UnMutRenderer>>> =
+    LazyLock::new(|| RwLock::new(Arc::new(UnMutRenderer::new())));
+
+// Resources are prepared on worker threads, keep them `Send + Sync`
+const _: fn() = || {
+    fn send_sync<T: Send + Sync>() {}
+    send_sync::<Renderer>();
+    send_sync::<RenderContext>();
+    send_sync::<mesh::Material>();
+    send_sync::<mesh::MaterialLayout>();
+    send_sync::<mesh::MeshRef>();
+    send_sync::<Texture>();
+    send_sync::<BindGroup>();
+    send_sync::<Buffer<u8>>();
+    send_sync::<RenderBundle>();
+};
 
 pub struct Renderer {
+    /// Device of pipelines, surfaces and resources from `create_*`
+    context: RenderContext,
     pipelines: Pipelines,
     pub(crate) needs_exit: bool,
+    /// Created on first OIT draw
+    oit: Option<Oit>,
+    profiler: GpuProfiler,
+    /// Counters of frame which is rendered now
+    frame_stats: RenderStats,
+    stats: RenderStats,
+    registry: ResourceRegistry,
+}
+
+/// `Renderer` of passes. Passes recorded in parallel read it for built pipelines,
+/// it is locked only for new pipeline variants, cameras and `Render::with_renderer`
+trait SharedRenderer {
+    fn with(&self, f: &mut dyn FnMut(&mut Renderer));
+    fn read(&self, f: &mut dyn FnMut(&Renderer));
+}
+impl SharedRenderer for RwLock<&mut Renderer> {
+    fn with(&self, f: &mut dyn FnMut(&mut Renderer)) {
+        f(&mut self.write().unwrap())
+    }
+    fn read(&self, f: &mut dyn FnMut(&Renderer)) {
+        f(&self.read().unwrap())
+    }
+}
+
+/// Surface and depth texture which pass draws into
+#[derive(Clone)]
+struct PassTarget<'t> {
+    surface_id: SurfaceId,
+    size: (u32, u32),
+    surface_format: TextureFormat,
+    view: &'t TextureView,
+    depth_texture: Option<&'t Texture>,
+}
+impl<'t> PassTarget<'t> {
+    fn new(
+        surface_id: SurfaceId,
+        output: &SurfaceTexture,
+        view: &'t TextureView,
+        depth_texture: Option<&'t Texture>,
+    ) -> Self {
+        let size = output.texture.size();
+        Self {
+            surface_id,
+            size: (size.width, size.height),
+            surface_format: output.texture.format(),
+            view,
+            depth_texture,
+        }
+    }
+}
+
+/// Pass on surface, it is cleared if `clear_color` is set
+fn begin_surface_pass<'e>(
+    encoder: &'e mut wgpu::CommandEncoder,
+    target: &PassTarget,
+    clear_color: Option<Color>,
+    clear_depth: bool,
+    label: &str,
+    queries: &PassQueries,
+) -> RenderPass<'e> {
+    let load = match clear_color {
+        Some(c) => wgpu::LoadOp::Clear(c.into()),
+        None => wgpu::LoadOp::Load,
+    };
+    let depth_load = if clear_depth {
+        wgpu::LoadOp::Clear(1.0)
+    } else {
+        wgpu::LoadOp::Load
+    };
+    let depth_view = target.depth_texture.map(|t| t.view());
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some(label),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: target.view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load,
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: depth_view.as_ref().map(|view| {
+            wgpu::RenderPassDepthStencilAttachment {
+                view,
+                depth_ops: Some(wgpu::Operations {
+                    load: depth_load,
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }
+        }),
+        occlusion_query_set: None,
+        timestamp_writes: queries.timestamp_writes(),
+    });
+    queries.begin(&mut render_pass);
+    render_pass
 }
 
 /// Rendering is here
 #[allow(dead_code)]
 pub struct Render<'a> {
-    output: &'a SurfaceTexture,
     view: TextureView,
     render_pass: RenderPass<'a>,
-    renderer: &'a mut Renderer,
+    renderer: &'a (dyn SharedRenderer + 'a),
+    context: RenderContext,
     surface_id: SurfaceId,
+    surface_size: (u32, u32),
+    surface_format: TextureFormat,
     camera_render: Option<CameraRender>,
+    depth_format: Option<TextureFormat>,
+    /// Format of pipelines, it is not surface format in OIT pass
+    format: TextureFormat,
+    state: BoundState,
+    draw_queue: Vec<DrawItem>,
+    oit_queue: Vec<DrawItem>,
+    /// Counters of this pass, added to frame stats on end of pass
+    stats: RenderStats,
+}
+
+impl<'a> Render<'a> {
+    fn new(
+        render_pass: RenderPass<'a>,
+        renderer: &'a (dyn SharedRenderer + 'a),
+        context: RenderContext,
+        target: &PassTarget,
+        format: TextureFormat,
+    ) -> Self {
+        Self {
+            view: target.view.clone(),
+            render_pass,
+            renderer,
+            context,
+            surface_id: target.surface_id.clone(),
+            surface_size: target.size,
+            surface_format: target.surface_format,
+            camera_render: None,
+            depth_format: target.depth_texture.map(|t| t.format()),
+            format,
+            state: BoundState::default(),
+            draw_queue: Vec::new(),
+            oit_queue: Vec::new(),
+            stats: RenderStats::default(),
+        }
+    }
+    /// Draws queue and ends pass, returns OIT draws
+    fn finish(mut self, queries: &PassQueries) -> Vec<DrawItem> {
+        self.flush_draw_queue();
+        queries.end(&mut self.render_pass);
+        let stats = std::mem::take(&mut self.stats);
+        self.with_renderer(|renderer| renderer.frame_stats.add_pass(&stats));
+        std::mem::take(&mut self.oit_queue)
+    }
 }
 
 impl Render<'_> {
     pub fn set_camera(&mut self, camera: &mut impl Camera) {
         let size = self.get_surface_size();
-        self.camera_render = Some(camera.get_render_global(&mut self.renderer, size).clone());
+        let camera_render = self.with_renderer(|r| camera.get_render_global(r, size).clone());
+        self.camera_render = Some(camera_render);
     }
     pub fn get_projection(&self) -> CameraProjection {
         self.camera_render.as_ref().unwrap().proj
     }
+    /// Depth of point for camera, bigger is farther.
+    /// Use it for `DrawItem::depth` of transparent draws. Camera must be set
+    pub fn view_depth(&self, position: Vec3) -> f32 {
+        let clip = self.camera_render.as_ref().unwrap().view_proj * position.extend(1.);
+        clip.z / clip.w
+    }
     pub fn use_camera_uniform_at(&mut self, slot: u32) {
         let bg = self.camera_render.as_ref().unwrap().bindgroup.clone();
         self.set_bind_group(slot, &bg, &[]);
     }
-    /// Get renderer for init something
-    pub fn get_renderer(&self) -> &Renderer {
+    /// Use renderer for init something. Renderer is locked while `f` runs,
+    /// passes recorded in parallel wait for it
+    pub fn with_renderer<T>(&self, f: impl FnOnce(&mut Renderer) -> T) -> T {
+        let mut f = Some(f);
+        let mut out = None;
         self.renderer
+            .with(&mut |renderer| out = f.take().map(|f| f(renderer)));
+        out.unwrap()
+    }
+    /// Built pipeline is only read, renderer is locked to build new variant
+    fn pipeline(&self, key: &PipelineKey) -> Arc<RenderPipeline> {
+        let mut built = None;
+        self.renderer
+            .read(&mut |r| built = r.pipelines.get_built(self.format, key));
+        built.unwrap_or_else(|| self.with_renderer(|r| r.get_pipeline(self.format, key.clone())))
+    }
+    /// Device of renderer, for creating resources without locking renderer
+    pub fn context(&self) -> &RenderContext {
+        &self.context
     }
     /// Get surface size
     pub fn get_surface_size(&self) -> (u32, u32) {
-        let size = self.output.texture.size();
-        (size.width, size.height)
+        self.surface_size
     }
     /// Get surface id
     pub fn get_surface_id(&self) -> SurfaceId {
@@ -145,23 +390,165 @@ impl Render<'_> {
         bind_group: &BindGroup,
         offsets: &[DynamicOffset],
     ) {
+        if !self.state.set_bind_group(index, bind_group) && offsets.is_empty() {
+            return;
+        }
+        self.stats.bind_group_switches += 1;
         self.render_pass
-            .set_bind_group(index, &bind_group.group, offsets);
+            .set_bind_group(index, bind_group.group.as_ref(), offsets);
+        if !offsets.is_empty() {
+            // Offsets are not compared, so next set is not skipped
+            self.state.bind_groups[index as usize] = None;
+        }
     }
-    /// Set pipeline
-    pub fn set_pipeline(&mut self, id: PipelineId) {
-        self.render_pass
-            .set_pipeline(&self.renderer.get_pipeline(self.output.texture.format(), id));
+    /// Set pipeline, `PipelineId` or its variant
+    pub fn set_pipeline(&mut self, key: impl Into<PipelineKey>) {
+        let pipeline = self.pipeline(&key.into());
+        self.set_render_pipeline(pipeline);
+    }
+    /// Record draw, queued draws are done sorted at end of pass or on `flush_draw_queue`.
+    /// Uniforms are read on submit, so material drawn with other values in one frame
+    /// needs instances
+    pub fn queue_draw(&mut self, mut item: DrawItem) {
+        self.fill_bind_groups(&mut item);
+        self.draw_queue.push(item);
+    }
+    /// Record draw with weighted blended OIT, it is done after all other draws
+    /// of surface. Depth of `item` is not used, see `oit` module
+    pub fn queue_oit(&mut self, mut item: DrawItem) {
+        self.fill_bind_groups(&mut item);
+        item.pipeline.state.oit = true;
+        item.depth = None;
+        self.oit_queue.push(item);
+    }
+    fn fill_bind_groups(&self, item: &mut DrawItem) {
+        for (slot, bind_group) in self.state.bind_groups.iter().enumerate() {
+            let Some(bind_group) = bind_group else {
+                continue;
+            };
+            if !item.bind_groups.iter().any(|(s, _)| *s as usize == slot) {
+                item.bind_groups.push((slot as u32, bind_group.clone()));
+            }
+        }
+    }
+    /// Draw all queued draws now sorted by layer, pipeline and material
+    pub fn flush_draw_queue(&mut self) {
+        let items = std::mem::take(&mut self.draw_queue);
+        let items = draw_queue::sort_items(items, |key| self.pipeline(key));
+        for (item, pipeline) in items {
+            self.set_render_pipeline(pipeline);
+            for (slot, bind_group) in item.bind_groups.iter() {
+                self.set_bind_group(*slot, bind_group, &[]);
+            }
+            for (slot, buffer) in item.vertex_buffers.iter() {
+                self.set_vertex_buffer_untyped(buffer, *slot, ..);
+            }
+            if let Some((buffer, format)) = &item.index_buffer {
+                self.set_index_buffer_untyped(buffer, .., *format);
+            }
+            match item.call {
+                DrawCall::Draw {
+                    vertices,
+                    instances,
+                } => self.draw(vertices, instances),
+                DrawCall::DrawIndexed {
+                    indices,
+                    base_vertex,
+                    instances,
+                } => self.draw_indexed(indices, base_vertex, instances),
+            }
+        }
+    }
+    /// Replay recorded bundle
+    /// State (pipeline, bind groups, buffers) is cleared after it
+    pub fn execute_bundle(&mut self, bundle: &mut RenderBundle) {
+        let target = BundleTarget {
+            format: self.surface_format,
+            depth_format: self.depth_format,
+        };
+        let bundle = self.with_renderer(|r| bundle.get_for_target(r, target).clone());
+        self.render_pass.execute_bundles(std::iter::once(&bundle));
+        self.state = BoundState::default();
     }
     /// draw
     pub fn draw(&mut self, vertices: Range<u32>, instances: Range<u32>) {
+        self.stats.draw_calls += 1;
         self.render_pass.draw(vertices, instances);
     }
     /// draw with indicies
     pub fn draw_indexed(&mut self, vertices: Range<u32>, base_vertex: i32, instances: Range<u32>) {
+        self.stats.draw_calls += 1;
         self.render_pass
             .draw_indexed(vertices, base_vertex, instances);
     }
+    /// draw with arguments from buffer
+    pub fn draw_indirect(&mut self, buffer: &IndirectBuffer<DrawIndirectArgs>, index: u32) {
+        self.stats.draw_calls += 1;
+        self.render_pass.draw_indirect(
+            &buffer.as_buffer().wgpu_buffer,
+            IndirectBuffer::<DrawIndirectArgs>::offset_of(index),
+        );
+    }
+    /// draw with indicies and arguments from buffer
+    pub fn draw_indexed_indirect(
+        &mut self,
+        buffer: &IndirectBuffer<DrawIndexedIndirectArgs>,
+        index: u32,
+    ) {
+        self.stats.draw_calls += 1;
+        self.render_pass.draw_indexed_indirect(
+            &buffer.as_buffer().wgpu_buffer,
+            IndirectBuffer::<DrawIndexedIndirectArgs>::offset_of(index),
+        );
+    }
+    /// draw all `draws` from buffer
+    /// If `Features::MULTI_DRAW_INDIRECT` is not supported it is many `draw_indirect`
+    pub fn multi_draw_indirect(
+        &mut self,
+        buffer: &IndirectBuffer<DrawIndirectArgs>,
+        draws: Range<u32>,
+    ) {
+        if self
+            .context
+            .features()
+            .contains(Features::MULTI_DRAW_INDIRECT)
+        {
+            self.stats.draw_calls += 1;
+            self.render_pass.multi_draw_indirect(
+                &buffer.as_buffer().wgpu_buffer,
+                IndirectBuffer::<DrawIndirectArgs>::offset_of(draws.start),
+                draws.len() as u32,
+            );
+        } else {
+            for index in draws {
+                self.draw_indirect(buffer, index);
+            }
+        }
+    }
+    /// draw all `draws` from buffer with indicies
+    /// If `Features::MULTI_DRAW_INDIRECT` is not supported it is many `draw_indexed_indirect`
+    pub fn multi_draw_indexed_indirect(
+        &mut self,
+        buffer: &IndirectBuffer<DrawIndexedIndirectArgs>,
+        draws: Range<u32>,
+    ) {
+        if self
+            .context
+            .features()
+            .contains(Features::MULTI_DRAW_INDIRECT)
+        {
+            self.stats.draw_calls += 1;
+            self.render_pass.multi_draw_indexed_indirect(
+                &buffer.as_buffer().wgpu_buffer,
+                IndirectBuffer::<DrawIndexedIndirectArgs>::offset_of(draws.start),
+                draws.len() as u32,
+            );
+        } else {
+            for index in draws {
+                self.draw_indexed_indirect(buffer, index);
+            }
+        }
+    }
     /// set vertex buffer
     pub fn set_vertex_buffer<V: Zeroable + Pod>(
         &mut self,
@@ -174,8 +561,14 @@ impl Render<'_> {
             log::warn!("Buffer is not added!");
             return;
         }
-        self.render_pass
-            .set_vertex_buffer(slot, buffer.wgpu_buffer.slice(buffer_slice));
+        let range = draw_queue::byte_range(buffer_slice);
+        if self
+            .state
+            .set_vertex_buffer(slot, &buffer.wgpu_buffer, range)
+        {
+            self.render_pass
+                .set_vertex_buffer(slot, draw_queue::slice(&buffer.wgpu_buffer, range));
+        }
     }
     /// set index buffer need for `draw_indexed`
     pub fn set_index_buffer<V: Zeroable + Pod + GetIndexFormat>(
@@ -188,10 +581,7 @@ impl Render<'_> {
             log::warn!("Buffer is not added!");
             return;
         }
-        self.render_pass.set_index_buffer(
-            buffer.wgpu_buffer.slice(buffer_slice),
-            V::get_index_format(),
-        );
+        self.set_index_buffer_inner(&buffer.wgpu_buffer, buffer_slice, V::get_index_format());
     }
     /// set vertex buffer
     pub fn set_vertex_buffer_untyped(
@@ -205,8 +595,14 @@ impl Render<'_> {
             log::warn!("Buffer is not added!");
             return;
         }
-        self.render_pass
-            .set_vertex_buffer(slot, buffer.wgpu_buffer.slice(buffer_slice));
+        let range = draw_queue::byte_range(buffer_slice);
+        if self
+            .state
+            .set_vertex_buffer(slot, &buffer.wgpu_buffer, range)
+        {
+            self.render_pass
+                .set_vertex_buffer(slot, draw_queue::slice(&buffer.wgpu_buffer, range));
+        }
     }
     /// set index buffer need for `draw_indexed`
     pub fn set_index_buffer_untyped(
@@ -220,8 +616,33 @@ impl Render<'_> {
             log::warn!("Buffer is not added!");
             return;
         }
-        self.render_pass
-            .set_index_buffer(buffer.wgpu_buffer.slice(buffer_slice), index_format);
+        self.set_index_buffer_inner(&buffer.wgpu_buffer, buffer_slice, index_format);
+    }
+
+    fn set_index_buffer_inner(
+        &mut self,
+        buffer: &wgpu::Buffer,
+        buffer_slice: impl RangeBounds<u64>,
+        index_format: IndexFormat,
+    ) {
+        let range = draw_queue::byte_range(buffer_slice);
+        if self.state.set_index_buffer(buffer, range, index_format) {
+            self.render_pass
+                .set_index_buffer(draw_queue::slice(buffer, range), index_format);
+        }
+    }
+    fn set_render_pipeline(&mut self, pipeline: Arc<RenderPipeline>) {
+        if self
+            .state
+            .pipeline
+            .as_ref()
+            .is_some_and(|p| Arc::ptr_eq(p, &pipeline))
+        {
+            return;
+        }
+        self.stats.pipeline_switches += 1;
+        self.render_pass.set_pipeline(&pipeline);
+        self.state.pipeline = Some(pipeline);
     }
 }
 
@@ -247,7 +668,7 @@ impl Renderer {
         layout: Vec<BindGroupEntryLayout>,
         res: Vec<BindGroupEntryResources>,
     ) -> BindGroup {
-        BindGroup::new(layout, res)
+        BindGroup::new_in(&self.context, layout, res)
     }
     /// Create texture
     pub fn create_texture_from_bytes(
@@ -255,7 +676,8 @@ impl Renderer {
         bytes: &[u8],
         filter: FilterMode,
     ) -> Result<Texture, anyhow::Error> {
-        Texture::from_bytes(bytes, filter)
+        let img = image::load_from_memory(bytes)?;
+        Texture::from_image_in(&self.context, &img, filter, AddressMode::ClampToEdge)
     }
     /// Create texture from `image` crate
     pub fn create_texture_from_image(
@@ -263,20 +685,40 @@ impl Renderer {
         img: &DynamicImage,
         filter: FilterMode,
     ) -> Result<Texture, anyhow::Error> {
-        Texture::from_image(img, filter)
+        Texture::from_image_in(&self.context, img, filter, AddressMode::ClampToEdge)
     }
-
-    pub(crate) fn create_surface(&mut self, window: Arc<Window>) -> SurfaceId {
-        Surfaces::get().create_surface(window)
+    /// Create texture from `image` crate converted to format of `options`
+    pub fn create_texture_with_options(
+        &mut self,
+        img: &DynamicImage,
+        options: &TextureOptions,
+    ) -> Result<Texture, anyhow::Error> {
+        Texture::from_image_with_options_in(&self.context, img, options)
     }
-    pub(crate) fn get_surface(&self, id: SurfaceId) -> Arc<Surface<'_>> {
-        Surfaces::get().get_surface(id).wgpu_surface.clone()
+    /// Create empty texture, fill it with `Texture::write_region`
+    pub fn create_empty_texture(
+        &mut self,
+        size: UVec2,
+        usage: TextureUsages,
+        filter: FilterMode,
+    ) -> Texture {
+        Texture::empty_in(&self.context, size, usage, filter)
+    }
+    /// Device of renderer
+    pub fn context(&self) -> &RenderContext {
+        &self.context
+    }
+
+    pub(crate) fn get_surface(&self, id: SurfaceId) -> Arc<Surface<'static>> {
+        self.context.surfaces().get_surface(id).wgpu_surface.clone()
     }
     pub(crate) fn on_resize(&mut self, window_id: &WindowId, new_size: PhysicalSize<u32>) {
-        Surfaces::get().resize_window_surface(window_id, new_size);
+        self.context
+            .surfaces()
+            .resize_window_surface(self.context.device(), window_id, new_size);
     }
     pub fn get_surface_size(&self, surface: SurfaceId) -> (u32, u32) {
-        let size = Surfaces::get().get_surface(surface).size;
+        let size = self.context.surfaces().get_surface(surface).size;
         (size.width, size.height)
     }
 
@@ -290,23 +732,122 @@ impl Renderer {
         vertices: Vec<V>,
         usage: BufferUsages,
     ) -> Buffer<V> {
-        Buffer::<V>::new(vertices, usage)
+        Buffer::<V>::new_in(&self.context, vertices, usage)
+    }
+    /// Texture which is created again from `image` after device is lost
+    pub fn register_texture(
+        &mut self,
+        image: DynamicImage,
+        filter: FilterMode,
+    ) -> Result<Registered<Texture>, anyhow::Error> {
+        let texture =
+            Texture::from_image_in(&self.context, &image, filter, AddressMode::ClampToEdge)?;
+        Ok(self.registry.insert(texture, move |context| {
+            Texture::from_image_in(context, &image, filter, AddressMode::ClampToEdge)
+        }))
+    }
+    /// Buffer which is created again with its contents after device is lost.
+    /// Update it with `Registered::update` so contents are kept
+    pub fn register_buffer<V: Pod + Zeroable + Send>(
+        &mut self,
+        vertices: Vec<V>,
+        usage: BufferUsages,
+    ) -> Registered<Buffer<V>> {
+        let buffer = Buffer::new_in(&self.context, vertices.clone(), usage);
+        self.registry.insert(buffer, move |context| {
+            Ok(Buffer::new_in(context, vertices.clone(), usage))
+        })
+    }
+    /// Anything which is created again with `create` after device is lost,
+    /// `create` gets context of renderer
+    pub fn register<T: Send + 'static>(
+        &mut self,
+        create: impl Fn(&RenderContext) -> Result<T, anyhow::Error> + Send + 'static,
+    ) -> Result<Registered<T>, anyhow::Error> {
+        let value = create(&self.context)?;
+        Ok(self.registry.insert(value, create))
+    }
+    /// Device was lost, app calls `recover_device` on next frame
+    pub fn is_device_lost(&self) -> bool {
+        self.context.is_lost()
+    }
+    /// Creates instance, adapter, device and surfaces again.
+    /// If renderer used global context, global one is replaced too.
+    /// Pipelines and registered resources are created again on new device,
+    /// everything else (bind groups, materials, meshes, cameras) app creates in
+    /// `CatApp::device_recreated`
+    pub fn recover_device(&mut self) -> Result<(), anyhow::Error> {
+        let context = if self.context.is_global() {
+            UnMutRenderer::recreate()?;
+            RenderContext::global()
+        } else {
+            RenderContext::new()?
+        };
+        let mut surfaces = std::mem::replace(&mut *self.context.surfaces(), Surfaces::new());
+        surfaces.recreate_all(&context)?;
+        *context.surfaces() = surfaces;
+        self.context = context;
+        self.pipelines.recreate(self.context.clone());
+        self.oit = None;
+        self.profiler = GpuProfiler::new(self.context.clone());
+        self.registry.recreate(&self.context);
+        log::info!("Device is created again");
+        Ok(())
+    }
+    /// Create buffer with draw arguments
+    pub fn create_indirect_buffer<A: IndirectArgs>(
+        &self,
+        args: Vec<A>,
+        usage: BufferUsages,
+    ) -> IndirectBuffer<A> {
+        IndirectBuffer::new_in(&self.context, args, usage)
     }
     /// Is exists surface
     pub fn exists_surface(&self, surface: SurfaceId) -> bool {
-        Surfaces::get().exists(surface)
+        self.context.surfaces().exists(surface)
     }
-    /// Create pipeline
-    pub fn create_pipeline(&mut self, options: PipelineOptions) -> PipelineId {
+    /// Create pipeline, shaders are validated before
+    pub fn create_pipeline(&mut self, options: PipelineOptions) -> Result<PipelineId, ShaderError> {
         self.pipelines.create_pipeline(options)
     }
+    /// Bindings of `group` and vertex inputs of shaders
+    pub fn reflect_shader(
+        &self,
+        options: &mut PipelineOptions,
+        group: u32,
+    ) -> Result<ShaderReflection, ShaderError> {
+        self.pipelines.reflect(options, group)
+    }
+    /// Use pipeline cache saved in app data directory for every next pipeline.
+    /// Cache is saved on exit or with `save_pipeline_cache`.
+    /// Returns false if adapter doesn't support pipeline caches
+    pub fn enable_pipeline_cache(&mut self, app_name: &str) -> Result<bool, anyhow::Error> {
+        self.pipelines.enable_cache(app_name)
+    }
+    /// Save pipeline cache now
+    pub fn save_pipeline_cache(&self) -> Result<(), anyhow::Error> {
+        self.pipelines.save_cache()
+    }
+    /// Number of cached (samplers, bind groups), see `cache` module
+    pub fn cached_resources(&self) -> (usize, usize) {
+        self.context.cache.len()
+    }
+    /// Register module for `#include name` in shaders
+    pub fn register_shader_module(&mut self, name: &str, source: &str) {
+        self.pipelines.preprocessor.register_module(name, source);
+    }
+    /// Recompile pipelines created from shader files which were changed.
+    /// Called every frame by app
+    pub fn reload_changed_shaders(&mut self) {
+        self.pipelines.reload_changed_shaders();
+    }
     /// Get pipeline
     pub fn get_pipeline(
         &mut self,
         format: TextureFormat,
-        pipeline_id: PipelineId,
-    ) -> Rc<RenderPipeline> {
-        self.pipelines.get_pipeline_for_surface(format, pipeline_id)
+        key: impl Into<PipelineKey>,
+    ) -> Arc<RenderPipeline> {
+        self.pipelines.get_pipeline_for_surface(format, key.into())
     }
     // pub fn start_render_for_camera<C: Camera>(
     //     &mut self,
@@ -330,7 +871,6 @@ impl Renderer {
     //     );
     // }
     /// Renderings starts here!
-    #[allow(unused_assignments)]
     pub fn start_render_for_surface(
         &mut self,
         surface_id: SurfaceId,
@@ -338,30 +878,72 @@ impl Renderer {
         depth_texture: Option<&Texture>,
         mut commands_sender: impl FnMut(&mut Render),
     ) {
-        let renderer = UnMutRenderer::get();
+        let Some(output) = self.acquire_output(surface_id.clone()) else {
+            return;
+        };
+        let view = output
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let target = PassTarget::new(surface_id, &output, &view, depth_texture);
+        let context = self.context.clone();
+        let mut encoder =
+            context
+                .device()
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Render Encoder"),
+                });
+        let queries = self.profiler.begin_pass("Render Pass");
+        let oit_items = {
+            let renderer = RwLock::new(&mut *self);
+            let render_pass = begin_surface_pass(
+                &mut encoder,
+                &target,
+                clear_color,
+                true,
+                "Render Pass",
+                &queries,
+            );
+            let mut render = Render::new(
+                render_pass,
+                &renderer,
+                context.clone(),
+                &target,
+                target.surface_format,
+            );
+            (commands_sender)(&mut render);
+            render.finish(&queries)
+        };
+        if !oit_items.is_empty() {
+            self.render_oit(&mut encoder, &target, oit_items);
+        }
+        context.queue().submit(std::iter::once(encoder.finish()));
+        output.present();
+    }
+    /// Texture of surface for this frame, `None` if frame can't be rendered
+    fn acquire_output(&mut self, surface_id: SurfaceId) -> Option<SurfaceTexture> {
         if !self.exists_surface(surface_id.clone()) {
             log::error!("Surface doesn't exists {:?}", surface_id);
             log::warn!("Render stoped.");
-            return;
+            return None;
         }
         if self.needs_exit {
-            return;
+            return None;
         }
-        let mut needs_exit = false;
-
-        let surface = self.get_surface(surface_id.clone());
-        let output = match surface.get_current_texture() {
-            Ok(o) => o,
+        let output = self.get_surface(surface_id.clone()).get_current_texture();
+        match output {
+            Ok(o) => Some(o),
             Err(e) => {
                 match e {
                     // Reconfigure the surface if it's lost or outdated
                     wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated => {
-                        // TODO state.resize(state.size)
+                        self.context
+                            .surfaces()
+                            .reconfigure(self.context.device(), surface_id);
                     }
                     // The system is out of memory, we should probably quit
                     wgpu::SurfaceError::OutOfMemory => {
                         log::error!("Out of memory :(");
-                        needs_exit = true;
+                        self.needs_exit = true;
                     }
 
                     // This happens when the a frame takes too long to present
@@ -371,69 +953,122 @@ impl Renderer {
                     wgpu::SurfaceError::Other => {}
                 }
                 log::error!("Failed to start render: {}", e);
-                return;
+                None
             }
+        }
+    }
+
+    /// Accumulates OIT draws with depth of opaque ones and composites them over surface
+    fn render_oit(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &PassTarget,
+        items: Vec<DrawItem>,
+    ) {
+        let oit = match &mut self.oit {
+            Some(oit) => oit,
+            None => self
+                .oit
+                .insert(Oit::new(&self.context, &mut self.pipelines)),
         };
-        drop(surface);
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
-        let mut encoder = renderer
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("Render Encoder"),
-            });
+        let composite = oit.composite.clone();
+        let targets = oit.targets(target.surface_id.clone(), target.size);
+        let context = self.context.clone();
+        let depth_view = target.depth_texture.map(|t| t.view());
         {
-            let load = match clear_color {
-                Some(c) => wgpu::LoadOp::Clear(c.into()),
-                None => wgpu::LoadOp::Load,
-            };
-            let mut depth_stencil = None;
-            if let Some(t) = depth_texture {
-                depth_stencil = Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: &t.view,
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: wgpu::StoreOp::Store,
-                    }),
-                    stencil_ops: None,
-                });
-            }
+            let queries = self.profiler.begin_pass("OIT Pass");
             let render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load,
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: depth_stencil,
+                label: Some("OIT Pass"),
+                color_attachments: &[
+                    Some(wgpu::RenderPassColorAttachment {
+                        view: &targets.accum,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                            store: wgpu::StoreOp::Store,
+                        },
+                    }),
+                    Some(wgpu::RenderPassColorAttachment {
+                        view: &targets.reveal,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                            store: wgpu::StoreOp::Store,
+                        },
+                    }),
+                ],
+                depth_stencil_attachment: depth_view.as_ref().map(|view| {
+                    wgpu::RenderPassDepthStencilAttachment {
+                        view,
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: wgpu::StoreOp::Store,
+                        }),
+                        stencil_ops: None,
+                    }
+                }),
                 occlusion_query_set: None,
-                timestamp_writes: None,
+                timestamp_writes: queries.timestamp_writes(),
             });
-            let mut render = Render {
-                output: &output,
-                view,
-                render_pass,
-                renderer: self,
-                surface_id,
-
-                camera_render: None,
-            };
-            (commands_sender)(&mut render);
+            let renderer = RwLock::new(&mut *self);
+            let mut render =
+                Render::new(render_pass, &renderer, context, target, oit::ACCUM_FORMAT);
+            queries.begin(&mut render.render_pass);
+            render.draw_queue = items;
+            render.finish(&queries);
         }
-        renderer.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
-
-        self.needs_exit = needs_exit;
+        let pipeline = self.get_pipeline(target.surface_format, composite);
+        let queries = self.profiler.begin_pass("OIT Composite Pass");
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("OIT Composite Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: queries.timestamp_writes(),
+        });
+        queries.begin(&mut render_pass);
+        render_pass.set_pipeline(&pipeline);
+        render_pass.set_bind_group(0, Some(&targets.bind_group), &[]);
+        render_pass.draw(0..3, 0..1);
+        queries.end(&mut render_pass);
+        self.frame_stats.pipeline_switches += 1;
+        self.frame_stats.bind_group_switches += 1;
+        self.frame_stats.draw_calls += 1;
+    }
+    /// Statistics of last frame
+    pub fn render_stats(&self) -> &RenderStats {
+        &self.stats
+    }
+    /// Called by app after `CatApp::render`
+    pub(crate) fn end_frame(&mut self) {
+        let mut stats = std::mem::take(&mut self.frame_stats);
+        self.context.counters.take(&mut stats);
+        self.profiler.end_frame(&mut stats);
+        self.stats = stats;
+        self.context.cache.end_frame();
     }
 
     pub(crate) async fn new() -> Self {
+        Self::with_context(RenderContext::global())
+    }
+    /// Renderer which creates everything on `context`
+    pub fn with_context(context: RenderContext) -> Self {
         Self {
             needs_exit: false,
-            pipelines: Pipelines::new(),
+            pipelines: Pipelines::new(context.clone()),
+            oit: None,
+            profiler: GpuProfiler::new(context.clone()),
+            context,
+            frame_stats: RenderStats::default(),
+            stats: RenderStats::default(),
+            registry: ResourceRegistry::default(),
         }
     }
 }
@@ -0,0 +1,201 @@
+//! Weighted blended order-independent transparency.
+//! Draws from `Render::queue_oit` are done after main pass into accumulation and
+//! revealage targets, then they are composited over surface. Order of them is not matter.
+//! Fragment shader must return `OitOutput` from `#include cat_render::oit`
+
+use std::{collections::HashMap, sync::Arc};
+
+use wgpu::{
+    BlendComponent, BlendFactor, BlendOperation, BlendState, ColorTargetState, ColorWrites,
+    TextureFormat,
+};
+
+use super::{
+    bind_group::{BindGroupEntryLayout, BindGroupLayout},
+    context::RenderContext,
+    render_pipeline::{PipelineId, PipelineOptions, Pipelines},
+    surface::SurfaceId,
+};
+
+pub(crate) const ACCUM_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+pub(crate) const REVEAL_FORMAT: TextureFormat = TextureFormat::R8Unorm;
+
+pub(crate) const OIT_MODULE: &str = "struct OitOutput {
+    @location(0) accum: vec4<f32>,
+    @location(1) reveal: f32,
+};
+// `depth` is z of @builtin(position), from 0 (near) to 1 (far)
+fn oit_output(color: vec4<f32>, depth: f32) -> OitOutput {
+    let weight = clamp(
+        pow(min(1.0, color.a * 10.0) + 0.01, 3.0) * 1e8 * pow(1.0 - depth * 0.9, 3.0),
+        1e-2,
+        3e3,
+    );
+    var out: OitOutput;
+    out.accum = vec4<f32>(color.rgb * color.a, color.a) * weight;
+    out.reveal = color.a;
+    return out;
+}
+";
+
+const COMPOSITE_SHADER: &str = "@group(0) @binding(0)
+var accum_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var reveal_texture: texture_2d<f32>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(position.xy);
+    let reveal = textureLoad(reveal_texture, pixel, 0).r;
+    if reveal >= 1.0 {
+        discard;
+    }
+    let accum = textureLoad(accum_texture, pixel, 0);
+    return vec4<f32>(accum.rgb / max(accum.a, 1e-5), 1.0 - reveal);
+}
+";
+
+/// Color targets of pipelines for OIT pass
+pub(crate) fn targets() -> [Option<ColorTargetState>; 2] {
+    [
+        Some(ColorTargetState {
+            format: ACCUM_FORMAT,
+            blend: Some(BlendState {
+                color: BlendComponent {
+                    src_factor: BlendFactor::One,
+                    dst_factor: BlendFactor::One,
+                    operation: BlendOperation::Add,
+                },
+                alpha: BlendComponent {
+                    src_factor: BlendFactor::One,
+                    dst_factor: BlendFactor::One,
+                    operation: BlendOperation::Add,
+                },
+            }),
+            write_mask: ColorWrites::ALL,
+        }),
+        Some(ColorTargetState {
+            format: REVEAL_FORMAT,
+            blend: Some(BlendState {
+                color: BlendComponent {
+                    src_factor: BlendFactor::Zero,
+                    dst_factor: BlendFactor::OneMinusSrc,
+                    operation: BlendOperation::Add,
+                },
+                alpha: BlendComponent::REPLACE,
+            }),
+            write_mask: ColorWrites::RED,
+        }),
+    ]
+}
+
+/// Composite pipeline and targets for every surface
+pub(crate) struct Oit {
+    pub(crate) composite: PipelineId,
+    layout: Arc<wgpu::BindGroupLayout>,
+    targets: HashMap<SurfaceId, OitTargets>,
+    context: RenderContext,
+}
+
+#[derive(Clone)]
+pub(crate) struct OitTargets {
+    size: (u32, u32),
+    pub(crate) accum: wgpu::TextureView,
+    pub(crate) reveal: wgpu::TextureView,
+    pub(crate) bind_group: wgpu::BindGroup,
+}
+
+impl Oit {
+    pub(crate) fn new(context: &RenderContext, pipelines: &mut Pipelines) -> Self {
+        let layout =
+            BindGroupLayout::new_in(context, vec![texture_entry(0), texture_entry(1)]).layout();
+        let composite = pipelines
+            .create_pipeline(PipelineOptions {
+                vertex_shader: COMPOSITE_SHADER.to_string(),
+                bind_group_layouts: vec![layout.clone()],
+                frag_blend: Some(BlendState::ALPHA_BLENDING),
+                primitive: wgpu::PrimitiveState {
+                    cull_mode: None,
+                    ..PipelineOptions::default().primitive
+                },
+                ..Default::default()
+            })
+            .expect("OIT composite shader is invalid");
+        Self {
+            composite,
+            layout,
+            targets: HashMap::new(),
+            context: context.clone(),
+        }
+    }
+    /// Targets are created again when size of surface is changed
+    pub(crate) fn targets(&mut self, surface: SurfaceId, size: (u32, u32)) -> OitTargets {
+        if let Some(targets) = self.targets.get(&surface) {
+            if targets.size == size {
+                return targets.clone();
+            }
+        }
+        let device = self.context.device();
+        let create = |format, label| {
+            device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size: wgpu::Extent3d {
+                        width: size.0,
+                        height: size.1,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                        | wgpu::TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                })
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        };
+        let accum = create(ACCUM_FORMAT, "OIT accumulation");
+        let reveal = create(REVEAL_FORMAT, "OIT revealage");
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("OIT composite"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&accum),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&reveal),
+                },
+            ],
+        });
+        let targets = OitTargets {
+            size,
+            accum,
+            reveal,
+            bind_group,
+        };
+        self.targets.insert(surface, targets.clone());
+        targets
+    }
+}
+
+fn texture_entry(binding: u32) -> BindGroupEntryLayout {
+    BindGroupEntryLayout {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+    }
+}
@@ -0,0 +1,118 @@
+//! Frame split into jobs which are recorded in parallel, every job has own
+//! command encoder and `Render`. Command buffers are submitted in order of jobs,
+//! so later job draws over earlier one. Only first job clears surface and depth.
+//! Jobs only read renderer for built pipelines, it is locked for new pipeline
+//! variants, cameras and `Render::with_renderer`
+
+use std::sync::RwLock;
+
+use rayon::prelude::*;
+
+use super::{
+    begin_surface_pass, draw_queue::DrawItem, texture::Texture, Color, PassTarget, Render,
+    Renderer, SurfaceId,
+};
+
+/// Part of frame recorded on thread pool, see `Renderer::start_parallel_render_for_surface`
+pub struct RenderJob<'f> {
+    label: String,
+    commands: Box<dyn FnOnce(&mut Render) + Send + 'f>,
+}
+
+impl<'f> RenderJob<'f> {
+    /// Label is name of pass in `RenderStats::gpu_passes`
+    pub fn new(label: &str, commands: impl FnOnce(&mut Render) + Send + 'f) -> Self {
+        Self {
+            label: label.to_string(),
+            commands: Box::new(commands),
+        }
+    }
+}
+
+impl Renderer {
+    /// Records every job into own command encoder in parallel and submits them in order.
+    /// OIT draws of all jobs are composited after last job
+    pub fn start_parallel_render_for_surface(
+        &mut self,
+        surface_id: SurfaceId,
+        clear_color: Option<Color>,
+        depth_texture: Option<&Texture>,
+        mut jobs: Vec<RenderJob>,
+    ) {
+        let Some(output) = self.acquire_output(surface_id.clone()) else {
+            return;
+        };
+        let view = output
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let target = PassTarget::new(surface_id, &output, &view, depth_texture);
+        let context = self.context.clone();
+        // Surface is cleared even if there is nothing to draw
+        if jobs.is_empty() {
+            jobs.push(RenderJob::new("Clear Pass", |_| {}));
+        }
+        let jobs = jobs
+            .into_iter()
+            .map(|job| {
+                let queries = self.profiler.begin_pass(&job.label);
+                (job, queries)
+            })
+            .collect::<Vec<_>>();
+
+        let recorded = {
+            let renderer = RwLock::new(&mut *self);
+            jobs.into_par_iter()
+                .enumerate()
+                .map(|(index, (job, queries))| {
+                    let mut encoder =
+                        context
+                            .device()
+                            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                                label: Some(&job.label),
+                            });
+                    let first = index == 0;
+                    let clear_color = if first { clear_color } else { None };
+                    let oit_items = {
+                        let render_pass = begin_surface_pass(
+                            &mut encoder,
+                            &target,
+                            clear_color,
+                            first,
+                            &job.label,
+                            &queries,
+                        );
+                        let mut render = Render::new(
+                            render_pass,
+                            &renderer,
+                            context.clone(),
+                            &target,
+                            target.surface_format,
+                        );
+                        (job.commands)(&mut render);
+                        render.finish(&queries)
+                    };
+                    (encoder.finish(), oit_items)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut command_buffers = Vec::with_capacity(recorded.len() + 1);
+        let mut oit_items: Vec<DrawItem> = Vec::new();
+        for (buffer, items) in recorded {
+            command_buffers.push(buffer);
+            oit_items.extend(items);
+        }
+        if !oit_items.is_empty() {
+            let mut encoder =
+                context
+                    .device()
+                    .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                        label: Some("OIT Encoder"),
+                    });
+            self.render_oit(&mut encoder, &target, oit_items);
+            command_buffers.push(encoder.finish());
+        }
+        context.queue().submit(command_buffers);
+        output.present();
+    }
+}
@@ -2,7 +2,6 @@
 
 use std::borrow::Borrow;
 use std::collections::HashMap;
-use std::rc::Rc;
 use std::sync::Arc;
 
 use wgpu::{
@@ -25,7 +24,7 @@ pub struct Pipeline {
     frag_shader: ShaderModule,
     render_pipeline_layout: PipelineLayout,
     options: PipelineOptions,
-    builded: HashMap<TextureFormat, Rc<RenderPipeline>>,
+    builded: HashMap<TextureFormat, Arc<RenderPipeline>>,
 }
 impl Pipelines {
     pub fn new() -> Self {
@@ -39,7 +38,7 @@ impl Pipelines {
         format: TextureFormat,
         pipeline_id: PipelineId,
         renderer: &Renderer,
-    ) -> Rc<RenderPipeline> {
+    ) -> Arc<RenderPipeline> {
         let pipeline = self
             .pipelines
             .get_mut(&pipeline_id)
@@ -77,7 +76,7 @@ impl Pipelines {
                         });
                 pipeline
                     .builded
-                    .insert(format.clone(), Rc::new(render_pipeline));
+                    .insert(format.clone(), Arc::new(render_pipeline));
             }
         };
         pipeline.builded.get(&format).unwrap().clone()
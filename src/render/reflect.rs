@@ -0,0 +1,401 @@
+//! Shader reflection: bindings of material group and vertex inputs.
+//! Used by `MaterialLayoutBuilder::from_shader` so layout is not written by hand.
+
+use std::sync::Mutex;
+
+use naga::{
+    valid::ModuleInfo, AddressSpace, Binding, ImageClass, ImageDimension, Module, ScalarKind,
+    ShaderStage, StorageAccess, TypeInner, VectorSize,
+};
+use wgpu::{
+    BindingType, BufferBindingType, SamplerBindingType, ShaderStages, TextureSampleType,
+    TextureViewDimension, VertexAttribute, VertexBufferLayout, VertexFormat, VertexStepMode,
+};
+
+use super::shader::ShaderError;
+
+/// What is on binding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindingKind {
+    /// `var<uniform>`, size in bytes with padding
+    Uniform {
+        size: u32,
+    },
+    /// `var<storage>`, size of runtime array counts one element
+    Storage {
+        size: u32,
+        read_only: bool,
+    },
+    Texture {
+        view_dimension: TextureViewDimension,
+        sample_type: TextureSampleType,
+        multisampled: bool,
+    },
+    Sampler {
+        comparison: bool,
+    },
+}
+
+/// Binding of shader
+#[derive(Debug, Clone)]
+pub struct ReflectedBinding {
+    /// Name of variable
+    pub name: String,
+    pub binding: u32,
+    /// Stages which use it
+    pub visibility: ShaderStages,
+    pub kind: BindingKind,
+    /// Fields if buffer is struct
+    pub members: Vec<ReflectedMember>,
+}
+
+/// Field of uniform or storage struct
+#[derive(Debug, Clone)]
+pub struct ReflectedMember {
+    pub name: String,
+    /// Bytes from start of buffer
+    pub offset: u32,
+    pub size: u32,
+}
+impl ReflectedBinding {
+    /// Type for bind group layout
+    pub fn binding_type(&self) -> BindingType {
+        match self.kind {
+            BindingKind::Uniform { .. } => BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            BindingKind::Storage { read_only, .. } => BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            BindingKind::Texture {
+                view_dimension,
+                sample_type,
+                multisampled,
+            } => BindingType::Texture {
+                sample_type,
+                view_dimension,
+                multisampled,
+            },
+            BindingKind::Sampler { comparison: true } => {
+                BindingType::Sampler(SamplerBindingType::Comparison)
+            }
+            BindingKind::Sampler { comparison: false } => {
+                BindingType::Sampler(SamplerBindingType::Filtering)
+            }
+        }
+    }
+}
+
+/// Input of vertex entry point
+#[derive(Debug, Clone)]
+pub struct ReflectedVertexInput {
+    pub name: String,
+    pub location: u32,
+    pub format: VertexFormat,
+}
+
+/// Bindings of one group and vertex inputs of shader
+#[derive(Debug, Clone)]
+pub struct ShaderReflection {
+    /// Sorted by binding
+    pub bindings: Vec<ReflectedBinding>,
+    /// Sorted by location
+    pub vertex_inputs: Vec<ReflectedVertexInput>,
+}
+
+impl ShaderReflection {
+    /// Bindings of `group` from all modules, vertex inputs from first one
+    pub(crate) fn new(
+        modules: &[(Module, ModuleInfo)],
+        vertex_entry_point: &str,
+        group: u32,
+    ) -> Result<Self, ShaderError> {
+        let mut bindings: Vec<ReflectedBinding> = Vec::new();
+        // Textures used with sampler, others can be not filterable
+        let mut sampled = Vec::new();
+        for (module, info) in modules {
+            for i in 0..module.entry_points.len() {
+                for key in info.get_entry_point(i).sampling_set.iter() {
+                    match &module.global_variables[key.image].binding {
+                        Some(b) if b.group == group => sampled.push(b.binding),
+                        _ => {}
+                    }
+                }
+            }
+            for (handle, var) in module.global_variables.iter() {
+                let Some(binding) = &var.binding else {
+                    continue;
+                };
+                if binding.group != group {
+                    continue;
+                }
+                let name = var.name.clone().unwrap_or_default();
+                let kind = binding_kind(module, var.space, var.ty).ok_or_else(|| {
+                    reflect_error(format!("Binding `{}` has unsupported type", name))
+                })?;
+                let mut visibility = ShaderStages::NONE;
+                for (i, entry) in module.entry_points.iter().enumerate() {
+                    if !info.get_entry_point(i)[handle].is_empty() {
+                        visibility |= stage(entry.stage);
+                    }
+                }
+                match bindings.iter_mut().find(|b| b.binding == binding.binding) {
+                    Some(b) if b.kind != kind => {
+                        return Err(reflect_error(format!(
+                            "Binding {} is `{}` and `{}` with other type",
+                            b.binding, b.name, name
+                        )));
+                    }
+                    Some(b) => b.visibility |= visibility,
+                    None => bindings.push(ReflectedBinding {
+                        name,
+                        binding: binding.binding,
+                        visibility,
+                        kind,
+                        members: members(module, var.ty),
+                    }),
+                }
+            }
+        }
+        for b in bindings.iter_mut() {
+            if let BindingKind::Texture {
+                sample_type: TextureSampleType::Float { filterable },
+                multisampled: false,
+                ..
+            } = &mut b.kind
+            {
+                *filterable = sampled.contains(&b.binding);
+            }
+            // Unused bindings still must be in layout
+            if b.visibility.is_empty() {
+                b.visibility = ShaderStages::VERTEX_FRAGMENT;
+            }
+        }
+        bindings.sort_by_key(|b| b.binding);
+
+        let mut vertex_inputs = Vec::new();
+        if let Some((module, _)) = modules.first() {
+            if let Some(entry) = module
+                .entry_points
+                .iter()
+                .find(|e| e.name == vertex_entry_point && e.stage == ShaderStage::Vertex)
+            {
+                for arg in entry.function.arguments.iter() {
+                    let name = arg.name.clone().unwrap_or_default();
+                    match (&arg.binding, &module.types[arg.ty].inner) {
+                        (Some(binding), inner) => {
+                            push_vertex_input(&mut vertex_inputs, name, binding, inner)?;
+                        }
+                        (None, TypeInner::Struct { members, .. }) => {
+                            for member in members {
+                                if let Some(binding) = &member.binding {
+                                    push_vertex_input(
+                                        &mut vertex_inputs,
+                                        member.name.clone().unwrap_or_default(),
+                                        binding,
+                                        &module.types[member.ty].inner,
+                                    )?;
+                                }
+                            }
+                        }
+                        (None, _) => {}
+                    }
+                }
+            }
+        }
+        vertex_inputs.sort_by_key(|v| v.location);
+        Ok(Self {
+            bindings,
+            vertex_inputs,
+        })
+    }
+    /// Binding by variable name
+    pub fn binding(&self, name: &str) -> Option<&ReflectedBinding> {
+        self.bindings.iter().find(|b| b.name == name)
+    }
+    /// Sampler for texture: on next binding, or the only sampler of group
+    pub fn sampler_for(&self, texture: &ReflectedBinding) -> Option<&ReflectedBinding> {
+        let mut samplers = self
+            .bindings
+            .iter()
+            .filter(|b| matches!(b.kind, BindingKind::Sampler { .. }));
+        if let Some(s) = samplers.clone().find(|b| b.binding == texture.binding + 1) {
+            return Some(s);
+        }
+        match (samplers.next(), samplers.next()) {
+            (Some(s), None) => Some(s),
+            _ => None,
+        }
+    }
+    /// One vertex buffer with all inputs packed in order of location.
+    /// Attributes are leaked once for every different layout
+    pub fn vertex_buffer_layout(&self) -> VertexBufferLayout<'static> {
+        let mut offset = 0;
+        let mut attributes = Vec::with_capacity(self.vertex_inputs.len());
+        for input in self.vertex_inputs.iter() {
+            attributes.push(VertexAttribute {
+                format: input.format,
+                offset,
+                shader_location: input.location,
+            });
+            offset += input.format.size();
+        }
+        VertexBufferLayout {
+            array_stride: offset,
+            step_mode: VertexStepMode::Vertex,
+            attributes: intern_attributes(attributes),
+        }
+    }
+}
+
+fn reflect_error(message: String) -> ShaderError {
+    ShaderError {
+        message,
+        location: None,
+        column: None,
+        snippet: None,
+    }
+}
+
+fn stage(stage: ShaderStage) -> ShaderStages {
+    match stage {
+        ShaderStage::Vertex => ShaderStages::VERTEX,
+        ShaderStage::Fragment => ShaderStages::FRAGMENT,
+        ShaderStage::Compute => ShaderStages::COMPUTE,
+    }
+}
+
+fn binding_kind(
+    module: &Module,
+    space: AddressSpace,
+    ty: naga::Handle<naga::Type>,
+) -> Option<BindingKind> {
+    let inner = &module.types[ty].inner;
+    match (space, inner) {
+        (AddressSpace::Uniform, _) => Some(BindingKind::Uniform {
+            size: inner.size(module.to_ctx()),
+        }),
+        (AddressSpace::Storage { access }, _) => Some(BindingKind::Storage {
+            size: inner.size(module.to_ctx()),
+            read_only: !access.contains(StorageAccess::STORE),
+        }),
+        (
+            AddressSpace::Handle,
+            TypeInner::Image {
+                dim,
+                arrayed,
+                class,
+            },
+        ) => {
+            let view_dimension = match (dim, arrayed) {
+                (ImageDimension::D1, _) => TextureViewDimension::D1,
+                (ImageDimension::D2, false) => TextureViewDimension::D2,
+                (ImageDimension::D2, true) => TextureViewDimension::D2Array,
+                (ImageDimension::D3, _) => TextureViewDimension::D3,
+                (ImageDimension::Cube, false) => TextureViewDimension::Cube,
+                (ImageDimension::Cube, true) => TextureViewDimension::CubeArray,
+            };
+            let (sample_type, multisampled) = match class {
+                ImageClass::Sampled { kind, multi } => (
+                    match kind {
+                        ScalarKind::Sint => TextureSampleType::Sint,
+                        ScalarKind::Uint => TextureSampleType::Uint,
+                        // Filterable if it is sampled, see `ShaderReflection::new`
+                        _ => TextureSampleType::Float { filterable: false },
+                    },
+                    *multi,
+                ),
+                ImageClass::Depth { multi } => (TextureSampleType::Depth, *multi),
+                ImageClass::Storage { .. } => return None,
+            };
+            Some(BindingKind::Texture {
+                view_dimension,
+                sample_type,
+                multisampled,
+            })
+        }
+        (AddressSpace::Handle, TypeInner::Sampler { comparison }) => Some(BindingKind::Sampler {
+            comparison: *comparison,
+        }),
+        _ => None,
+    }
+}
+
+/// Attributes of `ShaderReflection::vertex_buffer_layout`, same ones are leaked only once
+static VERTEX_ATTRIBUTES: Mutex<Vec<&'static [VertexAttribute]>> = Mutex::new(Vec::new());
+
+fn intern_attributes(attributes: Vec<VertexAttribute>) -> &'static [VertexAttribute] {
+    let mut interned = VERTEX_ATTRIBUTES.lock().unwrap();
+    if let Some(found) = interned.iter().find(|a| **a == attributes.as_slice()) {
+        return found;
+    }
+    let leaked = Vec::leak(attributes);
+    interned.push(leaked);
+    leaked
+}
+
+fn members(module: &Module, ty: naga::Handle<naga::Type>) -> Vec<ReflectedMember> {
+    let TypeInner::Struct { members, .. } = &module.types[ty].inner else {
+        return Vec::new();
+    };
+    members
+        .iter()
+        .map(|m| ReflectedMember {
+            name: m.name.clone().unwrap_or_default(),
+            offset: m.offset,
+            size: module.types[m.ty].inner.size(module.to_ctx()),
+        })
+        .collect()
+}
+
+fn push_vertex_input(
+    inputs: &mut Vec<ReflectedVertexInput>,
+    name: String,
+    binding: &Binding,
+    inner: &TypeInner,
+) -> Result<(), ShaderError> {
+    let Binding::Location { location, .. } = binding else {
+        return Ok(());
+    };
+    let (scalar, size) = match inner {
+        TypeInner::Scalar(scalar) => (scalar, None),
+        TypeInner::Vector { size, scalar } => (scalar, Some(*size)),
+        _ => {
+            return Err(reflect_error(format!(
+                "Vertex input `{}` is not vector",
+                name
+            )))
+        }
+    };
+    use VectorSize::*;
+    let format = match (scalar.kind, scalar.width, size) {
+        (ScalarKind::Float, 4, None) => VertexFormat::Float32,
+        (ScalarKind::Float, 4, Some(Bi)) => VertexFormat::Float32x2,
+        (ScalarKind::Float, 4, Some(Tri)) => VertexFormat::Float32x3,
+        (ScalarKind::Float, 4, Some(Quad)) => VertexFormat::Float32x4,
+        (ScalarKind::Sint, 4, None) => VertexFormat::Sint32,
+        (ScalarKind::Sint, 4, Some(Bi)) => VertexFormat::Sint32x2,
+        (ScalarKind::Sint, 4, Some(Tri)) => VertexFormat::Sint32x3,
+        (ScalarKind::Sint, 4, Some(Quad)) => VertexFormat::Sint32x4,
+        (ScalarKind::Uint, 4, None) => VertexFormat::Uint32,
+        (ScalarKind::Uint, 4, Some(Bi)) => VertexFormat::Uint32x2,
+        (ScalarKind::Uint, 4, Some(Tri)) => VertexFormat::Uint32x3,
+        (ScalarKind::Uint, 4, Some(Quad)) => VertexFormat::Uint32x4,
+        _ => {
+            return Err(reflect_error(format!(
+                "Vertex input `{}` has unsupported type",
+                name
+            )))
+        }
+    };
+    inputs.push(ReflectedVertexInput {
+        name,
+        location: *location,
+        format,
+    });
+    Ok(())
+}
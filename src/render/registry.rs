@@ -0,0 +1,89 @@
+//! Resources which are created again after device is lost, see `Renderer::recover_device`.
+//! Registered resource keeps what is needed to create it (image, contents or closure),
+//! `Registered::get` gives resource on current device
+
+use std::sync::{Arc, Mutex, Weak};
+
+use bytemuck::{Pod, Zeroable};
+
+use super::{buffer::Buffer, context::RenderContext};
+
+/// Handle of resource from `Renderer::register*`, it is created again on new device
+pub struct Registered<T> {
+    entry: Arc<Mutex<Entry<T>>>,
+}
+impl<T> Clone for Registered<T> {
+    fn clone(&self) -> Self {
+        Self {
+            entry: self.entry.clone(),
+        }
+    }
+}
+
+/// Creates resource on context
+type Create<T> = Box<dyn Fn(&RenderContext) -> anyhow::Result<T> + Send>;
+
+struct Entry<T> {
+    value: T,
+    create: Create<T>,
+}
+
+impl<T: Clone> Registered<T> {
+    /// Resource on current device of renderer, get it again after `CatApp::device_recreated`
+    pub fn get(&self) -> T {
+        self.entry.lock().unwrap().value.clone()
+    }
+}
+impl<V: Pod + Zeroable + Send> Registered<Buffer<V>> {
+    /// Updates buffer and contents from which it is created again
+    pub fn update(&self, vertices: Vec<V>) {
+        let mut entry = self.entry.lock().unwrap();
+        let usage = entry.value.wgpu_buffer.usage();
+        entry.value.update(vertices.clone());
+        entry.create =
+            Box::new(move |context| Ok(Buffer::new_in(context, vertices.clone(), usage)));
+    }
+}
+
+trait Recreate: Send + Sync {
+    fn recreate(&self, context: &RenderContext);
+}
+impl<T: Send> Recreate for Mutex<Entry<T>> {
+    fn recreate(&self, context: &RenderContext) {
+        let mut entry = self.lock().unwrap();
+        match (entry.create)(context) {
+            Ok(value) => entry.value = value,
+            Err(e) => log::error!("Failed to create registered resource again: {}", e),
+        }
+    }
+}
+
+/// Registered resources, dropped ones are forgotten
+#[derive(Default)]
+pub(crate) struct ResourceRegistry {
+    entries: Vec<Weak<dyn Recreate>>,
+}
+impl ResourceRegistry {
+    /// `value` is first result of `create`
+    pub(crate) fn insert<T: Send + 'static>(
+        &mut self,
+        value: T,
+        create: impl Fn(&RenderContext) -> anyhow::Result<T> + Send + 'static,
+    ) -> Registered<T> {
+        let entry = Arc::new(Mutex::new(Entry {
+            value,
+            create: Box::new(create),
+        }));
+        self.entries.retain(|e| e.strong_count() > 0);
+        let recreate: Arc<dyn Recreate> = entry.clone();
+        self.entries.push(Arc::downgrade(&recreate));
+        Registered { entry }
+    }
+    /// Creates every live resource on `context`
+    pub(crate) fn recreate(&mut self, context: &RenderContext) {
+        self.entries.retain(|e| e.strong_count() > 0);
+        for entry in self.entries.iter().filter_map(Weak::upgrade) {
+            entry.recreate(context);
+        }
+    }
+}
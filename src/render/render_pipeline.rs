@@ -2,22 +2,44 @@
 
 use std::borrow::Borrow;
 use std::collections::HashMap;
-use std::rc::Rc;
+use std::hash::{Hash, Hasher};
 use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
 use wgpu::{
-    BindGroupLayout, BlendState, ColorWrites, DepthStencilState, MultisampleState, PipelineCache,
-    PipelineLayout, PrimitiveState, ShaderModule, TextureFormat, VertexBufferLayout,
+    BindGroupLayout, BlendState, ColorWrites, DepthStencilState, Face, MultisampleState,
+    PipelineCache, PipelineLayout, PolygonMode, PrimitiveState, PrimitiveTopology, ShaderModule,
+    TextureFormat, VertexBufferLayout,
 };
 pub use wgpu::{PipelineCompilationOptions, PipelineLayoutDescriptor};
 
 pub use wgpu::RenderPipeline;
 
-use super::UnMutRenderer;
+use crate::utils::fs::Filesystem;
+
+use super::{
+    bind_group,
+    context::RenderContext,
+    oit,
+    reflect::ShaderReflection,
+    shader::{self, ProcessedShader, ShaderError, ShaderPreprocessor},
+};
 
 pub(crate) struct Pipelines {
     pipelines: HashMap<PipelineId, Pipeline>,
     last_id: u32,
+    cache: Option<PipelineCacheFile>,
+    /// Name from `enable_cache`, cache is loaded again for new device
+    cache_app: Option<String>,
+    last_reload_check: Instant,
+    pub(crate) preprocessor: ShaderPreprocessor,
+    context: RenderContext,
+}
+
+/// Pipeline cache which is saved to file
+struct PipelineCacheFile {
+    cache: PipelineCache,
+    path: String,
 }
 
 pub struct Pipeline {
@@ -25,98 +47,113 @@ pub struct Pipeline {
     frag_shader: ShaderModule,
     render_pipeline_layout: PipelineLayout,
     options: PipelineOptions,
-    builded: HashMap<TextureFormat, Rc<RenderPipeline>>,
+    builded: HashMap<(TextureFormat, PipelineState), Arc<RenderPipeline>>,
+    watched: Vec<WatchedShader>,
+    context: RenderContext,
 }
 impl Pipelines {
-    pub fn new() -> Self {
+    pub fn new(context: RenderContext) -> Self {
         Self {
             pipelines: HashMap::new(),
             last_id: 0,
+            cache: None,
+            cache_app: None,
+            last_reload_check: Instant::now(),
+            preprocessor: ShaderPreprocessor::new(),
+            context,
         }
     }
+    /// Loads pipeline cache from app data directory, every next pipeline uses it.
+    /// Returns false if adapter doesn't support pipeline caches.
+    pub fn enable_cache(&mut self, app_name: &str) -> anyhow::Result<bool> {
+        let renderer = &self.context;
+        if !renderer.features().contains(wgpu::Features::PIPELINE_CACHE) {
+            return Ok(false);
+        }
+        // Key has adapter and driver so caches of other GPU are not loaded
+        let Some(key) = wgpu::util::pipeline_cache_key(&renderer.adapter().get_info()) else {
+            return Ok(false);
+        };
+        let fs = Filesystem::get();
+        let path = fs.data_dir(app_name)?.join(key);
+        let path = path
+            .to_str()
+            .ok_or_else(|| anyhow::anyhow!("Data directory is not utf-8"))?
+            .to_string();
+        let data = fs.read(&path).ok();
+        // SAFETY: data is written by `save_cache` from `PipelineCache::get_data`,
+        // with `fallback` wgpu checks it and ignores invalid data
+        let cache = unsafe {
+            renderer
+                .device()
+                .create_pipeline_cache(&wgpu::PipelineCacheDescriptor {
+                    label: Some("Pipeline cache"),
+                    data: data.as_deref(),
+                    fallback: true,
+                })
+        };
+        self.cache = Some(PipelineCacheFile { cache, path });
+        self.cache_app = Some(app_name.to_string());
+        Ok(true)
+    }
+    /// Writes pipeline cache into file
+    pub fn save_cache(&self) -> anyhow::Result<()> {
+        if let Some(PipelineCacheFile { cache, path }) = &self.cache {
+            if let Some(data) = cache.get_data() {
+                Filesystem::get().write_all_atomic(path, data)?;
+            }
+        }
+        Ok(())
+    }
+    /// Shader modules are shared by all variants of pipeline,
+    /// every format and state is builded once on first use
     pub fn get_pipeline_for_surface(
         &mut self,
         format: TextureFormat,
-        pipeline_id: PipelineId,
-    ) -> Rc<RenderPipeline> {
+        key: PipelineKey,
+    ) -> Arc<RenderPipeline> {
         let pipeline = self
             .pipelines
-            .get_mut(&pipeline_id)
+            .get_mut(&key.id)
             .expect("Pipeline doesn't exist");
-        match pipeline.builded.get(&format) {
+        let PipelineKey { id: _, state } = key;
+        let builded_key = (format, state);
+        match pipeline.builded.get(&builded_key) {
             Some(_) => {}
             None => {
-                let render_pipeline = UnMutRenderer::get().device.create_render_pipeline(
-                    &wgpu::RenderPipelineDescriptor {
-                        label: Some("Render Pipeline"),
-                        layout: Some(&pipeline.render_pipeline_layout),
-                        vertex: wgpu::VertexState {
-                            module: &pipeline.vert_shader,
-                            entry_point: Some(&pipeline.options.vertex_entry_point),
-                            buffers: pipeline.options.buffers.as_slice(),
-                            compilation_options: PipelineCompilationOptions::default(),
-                        },
-                        fragment: Some(wgpu::FragmentState {
-                            module: &pipeline.frag_shader,
-                            entry_point: Some(&pipeline.options.fragment_entry_point),
-                            targets: &[Some(wgpu::ColorTargetState {
-                                format,
-                                blend: pipeline.options.frag_blend,
-                                write_mask: pipeline.options.write_mask,
-                            })],
-                            compilation_options: PipelineCompilationOptions::default(),
-                        }),
-                        primitive: pipeline.options.primitive,
-                        depth_stencil: pipeline.options.depth_stencil.clone(),
-                        multisample: pipeline.options.multisample,
-                        multiview: None,
-                        cache: None,
-                    },
+                let render_pipeline = pipeline.build(
+                    &pipeline.vert_shader,
+                    &pipeline.frag_shader,
+                    format,
+                    &builded_key.1,
+                    self.cache.as_ref().map(|c| &c.cache),
                 );
-                pipeline.builded.insert(format, Rc::new(render_pipeline));
-            }
-        };
-        pipeline.builded.get(&format).unwrap().clone()
-    }
-    pub fn create_pipeline(&mut self, options: PipelineOptions) -> PipelineId {
-        let vert_shader =
-            UnMutRenderer::get()
-                .device
-                .create_shader_module(wgpu::ShaderModuleDescriptor {
-                    label: Some("Vertex Shader"),
-                    source: wgpu::ShaderSource::Wgsl(options.vertex_shader.clone().into()),
-                });
-        let frag_shader = match options.fragment_shader {
-            Some(ref f) => {
-                UnMutRenderer::get()
-                    .device
-                    .create_shader_module(wgpu::ShaderModuleDescriptor {
-                        label: Some("Vertex Shader"),
-                        source: wgpu::ShaderSource::Wgsl(f.into()),
-                    })
-            }
-            None => {
-                UnMutRenderer::get()
-                    .device
-                    .create_shader_module(wgpu::ShaderModuleDescriptor {
-                        label: Some("Vertex Shader"),
-                        source: wgpu::ShaderSource::Wgsl(options.vertex_shader.clone().into()),
-                    })
+                pipeline
+                    .builded
+                    .insert(builded_key.clone(), Arc::new(render_pipeline));
             }
         };
-        let render_pipeline_layout =
-            UnMutRenderer::get()
-                .device
-                .create_pipeline_layout(&PipelineLayoutDescriptor {
-                    label: Some("Pipeline layout"),
-                    bind_group_layouts: options
-                        .bind_group_layouts
-                        .iter()
-                        .map(|a| a.borrow())
-                        .collect::<Vec<&BindGroupLayout>>()
-                        .as_slice(),
-                    push_constant_ranges: &[], // Needs feature
-                });
+        pipeline.builded.get(&builded_key).unwrap().clone()
+    }
+    /// Pipeline if it is already built for format, doesn't build it
+    pub(crate) fn get_built(
+        &self,
+        format: TextureFormat,
+        key: &PipelineKey,
+    ) -> Option<Arc<RenderPipeline>> {
+        let pipeline = self.pipelines.get(&key.id)?;
+        pipeline.builded.get(&(format, key.state.clone())).cloned()
+    }
+    /// Shaders are preprocessed and validated, error points to line in shader
+    pub fn create_pipeline(
+        &mut self,
+        mut options: PipelineOptions,
+    ) -> Result<PipelineId, ShaderError> {
+        load_shader_files(&mut options)?;
+        let prepared = preprocess_shaders(&self.context, &self.preprocessor, &options)?;
+        let watched = watched_files(&options, &prepared);
+        let (vert_shader, frag_shader) = create_shader_modules(&self.context, prepared);
+        let render_pipeline_layout = create_pipeline_layout(&self.context, &options);
         self.pipelines.insert(
             PipelineId(self.last_id),
             Pipeline {
@@ -125,10 +162,370 @@ impl Pipelines {
                 render_pipeline_layout,
                 options,
                 builded: HashMap::new(),
+                watched,
+                context: self.context.clone(),
             },
         );
         self.last_id += 1;
-        PipelineId(self.last_id - 1)
+        Ok(PipelineId(self.last_id - 1))
+    }
+    /// Creates every pipeline again on `context` after old device was lost.
+    /// Pipelines with bind group layouts not from `BindGroupLayout` can't be created
+    pub fn recreate(&mut self, context: RenderContext) {
+        self.context = context;
+        if let Some(app_name) = self.cache_app.clone() {
+            self.cache = None;
+            if let Err(e) = self.enable_cache(&app_name) {
+                log::error!("Failed to load pipeline cache again: {}", e);
+            }
+        }
+        for (id, pipeline) in self.pipelines.iter_mut() {
+            if let Err(e) = pipeline.recreate(&self.context, &self.preprocessor) {
+                log::error!("Failed to create pipeline {:?} again: {}", id, e);
+            }
+        }
+    }
+    /// Bindings of `group` and vertex inputs of shaders in `options`.
+    /// Shaders from files are loaded into `options`
+    pub fn reflect(
+        &self,
+        options: &mut PipelineOptions,
+        group: u32,
+    ) -> Result<ShaderReflection, ShaderError> {
+        load_shader_files(options)?;
+        let prepared = preprocess_shaders(&self.context, &self.preprocessor, options)?;
+        ShaderReflection::new(&prepared.modules, &options.vertex_entry_point, group)
+    }
+    /// Recompiles pipelines whose shader files were changed.
+    /// Files are checked not more often than `RELOAD_CHECK_INTERVAL`
+    pub fn reload_changed_shaders(&mut self) {
+        if self.last_reload_check.elapsed() < RELOAD_CHECK_INTERVAL {
+            return;
+        }
+        self.last_reload_check = Instant::now();
+        let cache = self.cache.as_ref().map(|c| &c.cache);
+        for pipeline in self.pipelines.values_mut() {
+            let mut changed = false;
+            for watched in pipeline.watched.iter_mut() {
+                changed |= watched.is_changed();
+            }
+            if changed {
+                pipeline.reload(&self.preprocessor, cache);
+            }
+        }
+    }
+}
+
+impl Pipeline {
+    fn build(
+        &self,
+        vert_shader: &ShaderModule,
+        frag_shader: &ShaderModule,
+        format: TextureFormat,
+        state: &PipelineState,
+        cache: Option<&PipelineCache>,
+    ) -> RenderPipeline {
+        let options = &self.options;
+        let mut primitive = options.primitive;
+        if let Some(topology) = state.topology {
+            primitive.topology = topology;
+        }
+        if let Some(cull_mode) = state.cull_mode {
+            primitive.cull_mode = cull_mode;
+        }
+        if let Some(polygon_mode) = state.polygon_mode {
+            primitive.polygon_mode = polygon_mode;
+        }
+        let mut multisample = options.multisample;
+        if let Some(count) = state.sample_count {
+            // Passes have no multisampled targets to resolve from
+            assert_eq!(
+                count, 1,
+                "PipelineState::sample_count must be 1, MSAA is not supported"
+            );
+            multisample.count = count;
+        }
+        let constants = state.constants.iter().cloned().collect::<HashMap<_, _>>();
+        let mut depth_stencil = state
+            .depth_stencil
+            .clone()
+            .unwrap_or(options.depth_stencil.clone());
+        let surface_target = [Some(wgpu::ColorTargetState {
+            format,
+            blend: state.blend.unwrap_or(options.frag_blend),
+            write_mask: options.write_mask,
+        })];
+        let oit_targets = oit::targets();
+        let targets: &[_] = if state.oit {
+            if let Some(depth_stencil) = &mut depth_stencil {
+                depth_stencil.depth_write_enabled = false;
+            }
+            &oit_targets
+        } else {
+            &surface_target
+        };
+        let compilation_options = PipelineCompilationOptions {
+            constants: &constants,
+            ..Default::default()
+        };
+        self.context
+            .device()
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Render Pipeline"),
+                layout: Some(&self.render_pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: vert_shader,
+                    entry_point: Some(&options.vertex_entry_point),
+                    buffers: options.buffers.as_slice(),
+                    compilation_options: compilation_options.clone(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: frag_shader,
+                    entry_point: Some(&options.fragment_entry_point),
+                    targets,
+                    compilation_options,
+                }),
+                primitive,
+                depth_stencil,
+                multisample,
+                multiview: None,
+                cache: options.cache.as_ref().or(cache),
+            })
+    }
+    /// Shaders and layout on `context`, variants are builded again on use
+    fn recreate(
+        &mut self,
+        context: &RenderContext,
+        preprocessor: &ShaderPreprocessor,
+    ) -> anyhow::Result<()> {
+        let mut layouts = Vec::with_capacity(self.options.bind_group_layouts.len());
+        for layout in self.options.bind_group_layouts.iter() {
+            layouts.push(bind_group::recreate_layout(context, layout).ok_or_else(|| {
+                anyhow::anyhow!("bind group layout is not from `BindGroupLayout`")
+            })?);
+        }
+        self.options.bind_group_layouts = layouts;
+        // It was on old device
+        self.options.cache = None;
+        let (vert_shader, frag_shader) = create_shader_modules(
+            context,
+            preprocess_shaders(context, preprocessor, &self.options)?,
+        );
+        self.render_pipeline_layout = create_pipeline_layout(context, &self.options);
+        self.context = context.clone();
+        self.vert_shader = vert_shader;
+        self.frag_shader = frag_shader;
+        self.builded.clear();
+        Ok(())
+    }
+    /// Compiles shaders again and rebuilds every builded variant.
+    /// On error old shaders and pipelines are kept
+    fn reload(&mut self, preprocessor: &ShaderPreprocessor, cache: Option<&PipelineCache>) {
+        let mut options = PipelineOptions {
+            vertex_shader: self.options.vertex_shader.clone(),
+            fragment_shader: self.options.fragment_shader.clone(),
+            vertex_shader_path: self.options.vertex_shader_path.clone(),
+            fragment_shader_path: self.options.fragment_shader_path.clone(),
+            defines: self.options.defines.clone(),
+            ..Default::default()
+        };
+        if let Err(e) = load_shader_files(&mut options) {
+            log::error!("Shaders are not reloaded, old pipeline is used\n{}", e);
+            return;
+        }
+
+        let device = self.context.device();
+        let sources = match preprocess_shaders(&self.context, preprocessor, &options) {
+            Ok(sources) => sources,
+            Err(e) => {
+                log::error!("Failed to reload shaders, old pipeline is used\n{}", e);
+                return;
+            }
+        };
+        let watched = watched_files(&options, &sources);
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let (vert_shader, frag_shader) = create_shader_modules(&self.context, sources);
+        let mut builded = HashMap::new();
+        for (format, state) in self.builded.keys() {
+            let render_pipeline = self.build(&vert_shader, &frag_shader, *format, state, cache);
+            builded.insert((*format, state.clone()), Arc::new(render_pipeline));
+        }
+        if let Some(e) = pollster::block_on(device.pop_error_scope()) {
+            log::error!("Failed to reload shaders, old pipeline is used: {}", e);
+            return;
+        }
+        log::info!("Shaders reloaded");
+        self.options.vertex_shader = options.vertex_shader;
+        self.options.fragment_shader = options.fragment_shader;
+        self.vert_shader = vert_shader;
+        self.frag_shader = frag_shader;
+        self.builded = builded;
+        // Includes could be changed
+        self.watched = watched;
+    }
+}
+
+/// Reads shaders from paths of `options`, error names file which can't be read
+fn load_shader_files(options: &mut PipelineOptions) -> Result<(), ShaderError> {
+    if let Some(path) = &options.vertex_shader_path {
+        options.vertex_shader = read_shader(path)?;
+    }
+    if let Some(path) = &options.fragment_shader_path {
+        options.fragment_shader = Some(read_shader(path)?);
+    }
+    Ok(())
+}
+
+fn read_shader(path: &str) -> Result<String, ShaderError> {
+    Filesystem::get()
+        .read_to_string(path)
+        .map_err(|e| ShaderError {
+            message: format!("Failed to read shader {}: {}", path, e),
+            location: None,
+            column: None,
+            snippet: None,
+        })
+}
+
+/// Shader files of `options` and files they include to watch
+fn watched_files(options: &PipelineOptions, prepared: &PreparedShaders) -> Vec<WatchedShader> {
+    let mut paths: Vec<&String> = Vec::new();
+    let included = prepared
+        .fragment
+        .iter()
+        .chain(std::iter::once(&prepared.vertex))
+        .flat_map(|shader| shader.included_files());
+    for path in options
+        .vertex_shader_path
+        .iter()
+        .chain(options.fragment_shader_path.iter())
+        .chain(included)
+    {
+        if !paths.contains(&path) {
+            paths.push(path);
+        }
+    }
+    paths
+        .into_iter()
+        .map(|path| WatchedShader::new(path.clone()))
+        .collect()
+}
+
+/// Vertex and fragment (if it is separate) shaders after preprocessor and validation
+struct PreparedShaders {
+    vertex: ProcessedShader,
+    fragment: Option<ProcessedShader>,
+    /// Vertex module first
+    modules: Vec<(naga::Module, naga::valid::ModuleInfo)>,
+}
+
+fn preprocess_shaders(
+    context: &RenderContext,
+    preprocessor: &ShaderPreprocessor,
+    options: &PipelineOptions,
+) -> Result<PreparedShaders, ShaderError> {
+    let vertex = preprocessor.process(
+        &options.vertex_shader,
+        options
+            .vertex_shader_path
+            .as_deref()
+            .unwrap_or("vertex_shader"),
+        &options.defines,
+    )?;
+    let fragment = match options.fragment_shader {
+        Some(ref f) => Some(
+            preprocessor.process(
+                f,
+                options
+                    .fragment_shader_path
+                    .as_deref()
+                    .unwrap_or("fragment_shader"),
+                &options.defines,
+            )?,
+        ),
+        None => None,
+    };
+    let vertex_entry = (
+        options.vertex_entry_point.as_str(),
+        naga::ShaderStage::Vertex,
+    );
+    let fragment_entry = (
+        options.fragment_entry_point.as_str(),
+        naga::ShaderStage::Fragment,
+    );
+    let capabilities = shader::capabilities(context);
+    let modules = match fragment {
+        Some(ref f) => vec![
+            vertex.validate(&[vertex_entry], capabilities)?,
+            f.validate(&[fragment_entry], capabilities)?,
+        ],
+        None => vec![vertex.validate(&[vertex_entry, fragment_entry], capabilities)?],
+    };
+    Ok(PreparedShaders {
+        vertex,
+        fragment,
+        modules,
+    })
+}
+
+fn create_pipeline_layout(context: &RenderContext, options: &PipelineOptions) -> PipelineLayout {
+    context
+        .device()
+        .create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Pipeline layout"),
+            bind_group_layouts: options
+                .bind_group_layouts
+                .iter()
+                .map(|a| a.borrow())
+                .collect::<Vec<&BindGroupLayout>>()
+                .as_slice(),
+            push_constant_ranges: &[], // Needs feature
+        })
+}
+
+fn create_shader_modules(
+    context: &RenderContext,
+    PreparedShaders {
+        vertex, fragment, ..
+    }: PreparedShaders,
+) -> (ShaderModule, ShaderModule) {
+    let device = context.device();
+    let vert_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Vertex Shader"),
+        source: wgpu::ShaderSource::Wgsl(vertex.source.into()),
+    });
+    let frag_shader = match fragment {
+        Some(f) => device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Fragment Shader"),
+            source: wgpu::ShaderSource::Wgsl(f.source.into()),
+        }),
+        None => vert_shader.clone(),
+    };
+    (vert_shader, frag_shader)
+}
+
+const RELOAD_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Shader file which is checked for changes
+struct WatchedShader {
+    path: String,
+    modified: Option<SystemTime>,
+}
+impl WatchedShader {
+    fn new(path: String) -> Self {
+        let modified = Self::get_modified(&path);
+        Self { path, modified }
+    }
+    fn get_modified(path: &str) -> Option<SystemTime> {
+        std::fs::metadata(path).and_then(|m| m.modified()).ok()
+    }
+    fn is_changed(&mut self) -> bool {
+        let modified = Self::get_modified(&self.path);
+        if modified != self.modified {
+            self.modified = modified;
+            return true;
+        }
+        false
     }
 }
 
@@ -137,6 +534,13 @@ pub struct PipelineOptions {
     pub vertex_entry_point: String,
     pub fragment_shader: Option<String>,
     pub fragment_entry_point: String,
+    /// If set shader is loaded from file instead of `vertex_shader`
+    /// and reloaded when file or file included by it is changed
+    pub vertex_shader_path: Option<String>,
+    /// Same as `vertex_shader_path` for `fragment_shader`
+    pub fragment_shader_path: Option<String>,
+    /// Defines for shader preprocessor, see `shader` module
+    pub defines: Vec<(String, String)>,
     pub frag_blend: Option<BlendState>,
     pub write_mask: ColorWrites,
     pub bind_group_layouts: Vec<Arc<BindGroupLayout>>,
@@ -146,8 +550,17 @@ pub struct PipelineOptions {
     /// The multi-sampling properties of the pipeline.
     pub multisample: MultisampleState,
     /// The pipeline cache to use when creating this pipeline.
+    /// If `None` cache from `Renderer::enable_pipeline_cache` is used
     pub cache: Option<PipelineCache>,
 }
+impl Drop for Pipelines {
+    fn drop(&mut self) {
+        if let Err(e) = self.save_cache() {
+            log::error!("Failed to save pipeline cache: {}", e);
+        }
+    }
+}
+
 impl Default for PipelineOptions {
     fn default() -> Self {
         PipelineOptions {
@@ -155,6 +568,9 @@ impl Default for PipelineOptions {
             vertex_entry_point: String::from("vs_main"),
             fragment_shader: None,
             fragment_entry_point: String::from("fs_main"),
+            vertex_shader_path: None,
+            fragment_shader_path: None,
+            defines: vec![],
             frag_blend: Some(BlendState::REPLACE),
             write_mask: ColorWrites::all(),
             bind_group_layouts: vec![],
@@ -182,5 +598,87 @@ impl Default for PipelineOptions {
     }
 }
 
-#[derive(Hash, PartialEq, Eq, Clone)]
+#[derive(Hash, PartialEq, Eq, Clone, Debug)]
 pub struct PipelineId(u32);
+
+impl PipelineId {
+    #[cfg(test)]
+    pub(crate) fn for_test(id: u32) -> Self {
+        Self(id)
+    }
+    /// Variant of pipeline with other render state
+    pub fn with_state(&self, state: PipelineState) -> PipelineKey {
+        PipelineKey {
+            id: self.clone(),
+            state,
+        }
+    }
+}
+
+/// Render state which replaces state from `PipelineOptions`.
+/// `None` means value from `PipelineOptions`
+#[derive(Clone, Debug, Default)]
+pub struct PipelineState {
+    pub blend: Option<Option<BlendState>>,
+    pub topology: Option<PrimitiveTopology>,
+    pub cull_mode: Option<Option<Face>>,
+    /// Anything other than Fill requires Features::NON_FILL_POLYGON_MODE
+    pub polygon_mode: Option<PolygonMode>,
+    pub depth_stencil: Option<Option<DepthStencilState>>,
+    /// Sample count, only 1 is allowed because render passes use single-sample targets.
+    /// Building variant with other count panics
+    pub sample_count: Option<u32>,
+    /// Values of `override` constants in shader, compared by bits
+    pub constants: Vec<(String, f64)>,
+    /// Targets of OIT pass instead of surface, depth is not written. See `oit` module
+    pub oit: bool,
+}
+/// Constants are compared by bits as in `Hash`, so NaN equals itself and -0.0 isn't 0.0
+impl PartialEq for PipelineState {
+    fn eq(&self, other: &Self) -> bool {
+        self.blend == other.blend
+            && self.topology == other.topology
+            && self.cull_mode == other.cull_mode
+            && self.polygon_mode == other.polygon_mode
+            && self.depth_stencil == other.depth_stencil
+            && self.sample_count == other.sample_count
+            && self.oit == other.oit
+            && self.constants.len() == other.constants.len()
+            && self.constants.iter().zip(other.constants.iter()).all(
+                |((name, value), (other_name, other_value))| {
+                    name == other_name && value.to_bits() == other_value.to_bits()
+                },
+            )
+    }
+}
+impl Eq for PipelineState {}
+impl Hash for PipelineState {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.blend.hash(state);
+        self.topology.hash(state);
+        self.cull_mode.hash(state);
+        self.polygon_mode.hash(state);
+        self.depth_stencil.hash(state);
+        self.sample_count.hash(state);
+        self.oit.hash(state);
+        for (name, value) in self.constants.iter() {
+            name.hash(state);
+            value.to_bits().hash(state);
+        }
+    }
+}
+
+/// Pipeline with render state, see `PipelineId::with_state`
+#[derive(Hash, PartialEq, Eq, Clone, Debug)]
+pub struct PipelineKey {
+    pub id: PipelineId,
+    pub state: PipelineState,
+}
+impl From<PipelineId> for PipelineKey {
+    fn from(id: PipelineId) -> Self {
+        Self {
+            id,
+            state: PipelineState::default(),
+        }
+    }
+}
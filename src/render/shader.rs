@@ -0,0 +1,606 @@
+//! WGSL preprocessor used for every `PipelineOptions` shader.
+//!
+//! Directives (must be first on line):
+//! - `#include cat_render::camera` registered virtual module (also `cat_render::oit`)
+//! - `#include "common.wgsl"` file, relative to current file
+//! - `#define NAME` or `#define NAME value`, value replaces `NAME` in next lines,
+//!   it can use other defines
+//! - `#ifdef NAME`, `#ifndef NAME`, `#else`, `#endif`
+//!
+//! Every file or module is included only once.
+//!
+//! After preprocessing shader is validated, errors point to lines before preprocessing.
+
+use std::{collections::HashMap, fmt::Display, path::Path};
+
+use crate::utils::fs::Filesystem;
+
+use super::{oit, RenderContext};
+
+/// Camera uniform which `Render::use_camera_uniform_at` binds.
+/// Group can be changed with `#define CAMERA_GROUP n` before include
+const CAMERA_MODULE: &str = "#ifndef CAMERA_GROUP
+#define CAMERA_GROUP 1
+#endif
+struct CameraUniform {
+    proj: mat4x4<f32>,
+};
+@group(CAMERA_GROUP) @binding(0)
+var<uniform> cam_uni: CameraUniform;
+";
+
+/// Line in file before preprocessing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub file: String,
+    /// Starts from 1
+    pub line: u32,
+}
+impl Display for SourceLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+/// Error of preprocessor with place where it is
+#[derive(Debug, Clone)]
+pub struct PreprocessError {
+    pub location: SourceLocation,
+    pub message: String,
+}
+impl Display for PreprocessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+impl std::error::Error for PreprocessError {}
+
+/// Error in shader, readable instead of device panic
+#[derive(Debug, Clone)]
+pub struct ShaderError {
+    pub message: String,
+    pub location: Option<SourceLocation>,
+    /// Starts from 1
+    pub column: Option<u32>,
+    /// Line of code with error
+    pub snippet: Option<String>,
+}
+impl Display for ShaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "shader error: {}", self.message)?;
+        let Some(location) = &self.location else {
+            return Ok(());
+        };
+        match self.column {
+            Some(column) => writeln!(f, "  --> {}:{}", location, column)?,
+            None => writeln!(f, "  --> {}", location)?,
+        }
+        if let Some(snippet) = &self.snippet {
+            let number = location.line.to_string();
+            let pad = " ".repeat(number.len());
+            writeln!(f, "{} |", pad)?;
+            writeln!(f, "{} | {}", number, snippet)?;
+            if let Some(column) = self.column {
+                writeln!(f, "{} | {}^", pad, " ".repeat(column as usize - 1))?;
+            }
+        }
+        Ok(())
+    }
+}
+impl std::error::Error for ShaderError {}
+impl From<PreprocessError> for ShaderError {
+    fn from(e: PreprocessError) -> Self {
+        Self {
+            message: e.message,
+            location: Some(e.location),
+            column: None,
+            snippet: None,
+        }
+    }
+}
+
+/// Shader after preprocessing
+#[derive(Debug)]
+pub struct ProcessedShader {
+    pub source: String,
+    file: String,
+    /// Location of every line of `source`
+    locations: Vec<SourceLocation>,
+    /// Files from `#include "file"`
+    included_files: Vec<String>,
+}
+impl ProcessedShader {
+    /// Where line of processed source (from 1) was written
+    pub fn location(&self, line: u32) -> Option<&SourceLocation> {
+        self.locations.get(line.checked_sub(1)? as usize)
+    }
+    /// Paths of files which were included, modules are not in it
+    pub fn included_files(&self) -> &[String] {
+        &self.included_files
+    }
+    /// Parses and validates shader, `entry_points` must be in it.
+    /// `capabilities` are from device, see `capabilities`
+    pub fn validate(
+        &self,
+        entry_points: &[(&str, naga::ShaderStage)],
+        capabilities: naga::valid::Capabilities,
+    ) -> Result<(naga::Module, naga::valid::ModuleInfo), ShaderError> {
+        let module = naga::front::wgsl::parse_str(&self.source).map_err(|e| {
+            let mut message = e.message().to_string();
+            if let Some((_, label)) = e
+                .labels()
+                .find(|(_, l)| !l.is_empty() && !message.contains(l))
+            {
+                message = format!("{}: {}", message, label);
+            }
+            self.error(message, e.location(&self.source))
+        })?;
+        let info = naga::valid::Validator::new(naga::valid::ValidationFlags::all(), capabilities)
+            .validate(&module)
+            .map_err(|e| {
+                let mut message = e.as_inner().to_string();
+                let mut source = std::error::Error::source(e.as_inner());
+                while let Some(s) = source {
+                    message = format!("{}: {}", message, s);
+                    source = s.source();
+                }
+                self.error(message, e.location(&self.source))
+            })?;
+        for (name, stage) in entry_points {
+            if !module
+                .entry_points
+                .iter()
+                .any(|e| e.name == *name && e.stage == *stage)
+            {
+                // It is named in `PipelineOptions`, not in shader
+                return Err(ShaderError {
+                    message: format!(
+                        "{:?} entry point `{}` is not found in {}",
+                        stage, name, self.file
+                    ),
+                    location: None,
+                    column: None,
+                    snippet: None,
+                });
+            }
+        }
+        Ok((module, info))
+    }
+
+    fn error(&self, message: String, location: Option<naga::SourceLocation>) -> ShaderError {
+        let Some(location) = location else {
+            return ShaderError {
+                message,
+                location: None,
+                column: None,
+                snippet: None,
+            };
+        };
+        ShaderError {
+            message,
+            location: self.location(location.line_number).cloned(),
+            column: Some(location.line_position),
+            snippet: self
+                .source
+                .lines()
+                .nth(location.line_number as usize - 1)
+                .map(|l| l.to_string()),
+        }
+    }
+}
+
+/// What shaders can use on device of `context`, same as wgpu allows in
+/// `create_shader_module`. Shader which uses more fails in `validate`
+pub fn capabilities(context: &RenderContext) -> naga::valid::Capabilities {
+    use naga::valid::Capabilities as Caps;
+    use wgpu::{DownlevelFlags, Features};
+
+    let features = context.device().features();
+    let downlevel = context.adapter().get_downlevel_capabilities().flags;
+    let mut caps = Caps::empty();
+    for (cap, feature) in [
+        (Caps::PUSH_CONSTANT, Features::PUSH_CONSTANTS),
+        (Caps::FLOAT64, Features::SHADER_F64),
+        (Caps::PRIMITIVE_INDEX, Features::SHADER_PRIMITIVE_INDEX),
+        (
+            Caps::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING,
+            Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING,
+        ),
+        (
+            Caps::UNIFORM_BUFFER_AND_STORAGE_TEXTURE_ARRAY_NON_UNIFORM_INDEXING,
+            Features::UNIFORM_BUFFER_AND_STORAGE_TEXTURE_ARRAY_NON_UNIFORM_INDEXING,
+        ),
+        (
+            Caps::SAMPLER_NON_UNIFORM_INDEXING,
+            Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING,
+        ),
+        (
+            Caps::STORAGE_TEXTURE_16BIT_NORM_FORMATS,
+            Features::TEXTURE_FORMAT_16BIT_NORM,
+        ),
+        (Caps::MULTIVIEW, Features::MULTIVIEW),
+        (Caps::EARLY_DEPTH_TEST, Features::SHADER_EARLY_DEPTH_TEST),
+        (Caps::SHADER_INT64, Features::SHADER_INT64),
+        (
+            Caps::SHADER_INT64_ATOMIC_ALL_OPS,
+            Features::SHADER_INT64_ATOMIC_ALL_OPS,
+        ),
+        (Caps::TEXTURE_ATOMIC, Features::TEXTURE_ATOMIC),
+        (Caps::TEXTURE_INT64_ATOMIC, Features::TEXTURE_INT64_ATOMIC),
+        (Caps::SHADER_FLOAT32_ATOMIC, Features::SHADER_FLOAT32_ATOMIC),
+        (Caps::DUAL_SOURCE_BLENDING, Features::DUAL_SOURCE_BLENDING),
+        (Caps::SUBGROUP_BARRIER, Features::SUBGROUP_BARRIER),
+        (Caps::RAY_QUERY, Features::EXPERIMENTAL_RAY_QUERY),
+        (Caps::SUBGROUP_VERTEX_STAGE, Features::SUBGROUP_VERTEX),
+    ] {
+        caps.set(cap, features.contains(feature));
+    }
+    caps.set(
+        Caps::SHADER_INT64_ATOMIC_MIN_MAX,
+        features.intersects(
+            Features::SHADER_INT64_ATOMIC_MIN_MAX | Features::SHADER_INT64_ATOMIC_ALL_OPS,
+        ),
+    );
+    caps.set(
+        Caps::SUBGROUP,
+        features.intersects(Features::SUBGROUP | Features::SUBGROUP_VERTEX),
+    );
+    caps.set(
+        Caps::MULTISAMPLED_SHADING,
+        downlevel.contains(DownlevelFlags::MULTISAMPLED_SHADING),
+    );
+    caps.set(
+        Caps::CUBE_ARRAY_TEXTURES,
+        downlevel.contains(DownlevelFlags::CUBE_ARRAY_TEXTURES),
+    );
+    caps
+}
+
+pub struct ShaderPreprocessor {
+    modules: HashMap<String, String>,
+}
+
+impl Default for ShaderPreprocessor {
+    fn default() -> Self {
+        ShaderPreprocessor::new()
+    }
+}
+
+impl ShaderPreprocessor {
+    /// With built-in modules
+    pub fn new() -> Self {
+        let mut modules = HashMap::new();
+        modules.insert("cat_render::camera".to_string(), CAMERA_MODULE.to_string());
+        modules.insert("cat_render::oit".to_string(), oit::OIT_MODULE.to_string());
+        Self { modules }
+    }
+    /// Module for `#include name`
+    pub fn register_module(&mut self, name: &str, source: &str) {
+        self.modules.insert(name.to_string(), source.to_string());
+    }
+    /// `file` is name for errors and directory for includes
+    pub fn process(
+        &self,
+        source: &str,
+        file: &str,
+        defines: &[(String, String)],
+    ) -> Result<ProcessedShader, PreprocessError> {
+        let mut state = State {
+            defines: Vec::new(),
+            included: vec![file.to_string()],
+            out: ProcessedShader {
+                source: String::new(),
+                file: file.to_string(),
+                locations: Vec::new(),
+                included_files: Vec::new(),
+            },
+        };
+        for (name, value) in defines {
+            state.define(name, value);
+        }
+        self.process_file(source, file, &mut state)?;
+        Ok(state.out)
+    }
+
+    fn process_file(
+        &self,
+        source: &str,
+        file: &str,
+        state: &mut State,
+    ) -> Result<(), PreprocessError> {
+        // (is this branch active, was any branch of #ifdef active, was #else, place of #ifdef)
+        let mut branches: Vec<(bool, bool, bool, SourceLocation)> = Vec::new();
+        for (i, line) in source.lines().enumerate() {
+            let location = SourceLocation {
+                file: file.to_string(),
+                line: i as u32 + 1,
+            };
+            let error = |message: String| PreprocessError {
+                location: location.clone(),
+                message,
+            };
+            let active = branches.iter().all(|(a, _, _, _)| *a);
+            let trimmed = line.trim_start();
+            if !trimmed.starts_with('#') {
+                if active {
+                    let line = state
+                        .expand(line)
+                        .ok_or_else(|| error("#define refers to itself".to_string()))?;
+                    state.out.source.push_str(&line);
+                    state.out.source.push('\n');
+                    state.out.locations.push(location);
+                }
+                continue;
+            }
+            let mut parts = trimmed[1..].splitn(2, char::is_whitespace);
+            let directive = parts.next().unwrap_or("");
+            let arg = parts.next().unwrap_or("").trim();
+            match directive {
+                "ifdef" | "ifndef" => {
+                    let defined = state.is_defined(arg);
+                    let is = defined == (directive == "ifdef");
+                    branches.push((is, is, false, location));
+                }
+                "else" => {
+                    let (branch, was, had_else, _) = branches
+                        .last_mut()
+                        .ok_or_else(|| error("#else without #ifdef".to_string()))?;
+                    if *had_else {
+                        return Err(error("#else after #else".to_string()));
+                    }
+                    *had_else = true;
+                    *branch = !*was;
+                    *was = true;
+                }
+                "endif" => {
+                    branches
+                        .pop()
+                        .ok_or_else(|| error("#endif without #ifdef".to_string()))?;
+                }
+                _ if !active => {}
+                "define" => {
+                    let mut parts = arg.splitn(2, char::is_whitespace);
+                    let name = parts.next().unwrap_or("");
+                    if name.is_empty() {
+                        return Err(error("#define without name".to_string()));
+                    }
+                    let value = parts.next().unwrap_or("").trim();
+                    state.define(name, value);
+                }
+                "include" => {
+                    let (name, source) = if arg.starts_with('"') {
+                        let path = arg.trim_matches('"');
+                        let path = Path::new(file).parent().unwrap_or(Path::new("")).join(path);
+                        let path = path.to_string_lossy().to_string();
+                        let source = Filesystem::get()
+                            .read_to_string(&path)
+                            .map_err(|e| error(format!("Failed to include {}: {}", path, e)))?;
+                        if !state.out.included_files.contains(&path) {
+                            state.out.included_files.push(path.clone());
+                        }
+                        (path, source)
+                    } else {
+                        let source = self
+                            .modules
+                            .get(arg)
+                            .ok_or_else(|| error(format!("Unknown module {}", arg)))?;
+                        (arg.to_string(), source.clone())
+                    };
+                    if !state.included.contains(&name) {
+                        state.included.push(name.clone());
+                        self.process_file(&source, &name, state)?;
+                    }
+                }
+                _ => return Err(error(format!("Unknown directive #{}", directive))),
+            }
+        }
+        if let Some((_, _, _, location)) = branches.pop() {
+            return Err(PreprocessError {
+                location,
+                message: "#ifdef without #endif".to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+struct State {
+    /// In order of definition, so expansion doesn't depend on hashing
+    defines: Vec<(String, String)>,
+    included: Vec<String>,
+    out: ProcessedShader,
+}
+
+impl State {
+    fn define(&mut self, name: &str, value: &str) {
+        match self.defines.iter_mut().find(|(n, _)| n == name) {
+            Some((_, v)) => *v = value.to_string(),
+            None => self.defines.push((name.to_string(), value.to_string())),
+        }
+    }
+    fn is_defined(&self, name: &str) -> bool {
+        self.defines.iter().any(|(n, _)| n == name)
+    }
+    /// Replaces defines until nothing changes, so values can use other defines.
+    /// None if it doesn't end after `MAX_DEFINE_DEPTH` passes
+    fn expand(&self, line: &str) -> Option<String> {
+        let mut line = line.to_string();
+        for _ in 0..MAX_DEFINE_DEPTH {
+            let mut changed = false;
+            for (name, value) in self.defines.iter() {
+                if !value.is_empty() {
+                    let next = replace_word(&line, name, value);
+                    changed |= next != line;
+                    line = next;
+                }
+            }
+            if !changed {
+                return Some(line);
+            }
+        }
+        None
+    }
+}
+
+const MAX_DEFINE_DEPTH: usize = 16;
+
+/// Replaces identifier `name` but not parts of other identifiers
+fn replace_word(line: &str, name: &str, value: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut word = String::new();
+    for c in line.chars() {
+        if c.is_alphanumeric() || c == '_' {
+            word.push(c);
+            continue;
+        }
+        out.push_str(if word == name { value } else { &word });
+        word.clear();
+        out.push(c);
+    }
+    out.push_str(if word == name { value } else { &word });
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(source: &str, defines: &[(&str, &str)]) -> Result<ProcessedShader, PreprocessError> {
+        let defines = defines
+            .iter()
+            .map(|(n, v)| (n.to_string(), v.to_string()))
+            .collect::<Vec<_>>();
+        ShaderPreprocessor::new().process(source, "test.wgsl", &defines)
+    }
+
+    fn lines(shader: &ProcessedShader) -> Vec<&str> {
+        shader.source.lines().collect()
+    }
+
+    #[test]
+    fn nested_ifdef() {
+        let source = "#ifdef A
+a
+#ifdef B
+ab
+#else
+a_not_b
+#endif
+#else
+not_a
+#ifdef B
+not_a_b
+#endif
+#endif
+end";
+        let shader = process(source, &[("A", "")]).unwrap();
+        assert_eq!(lines(&shader), ["a", "a_not_b", "end"]);
+        let shader = process(source, &[("A", ""), ("B", "")]).unwrap();
+        assert_eq!(lines(&shader), ["a", "ab", "end"]);
+        let shader = process(source, &[("B", "")]).unwrap();
+        assert_eq!(lines(&shader), ["not_a", "not_a_b", "end"]);
+        let shader = process(source, &[]).unwrap();
+        assert_eq!(lines(&shader), ["not_a", "end"]);
+    }
+
+    #[test]
+    fn ifndef_and_define() {
+        let source = "#ifndef N
+#define N 4
+#endif
+let x = N + N_2;";
+        let shader = process(source, &[]).unwrap();
+        assert_eq!(lines(&shader), ["let x = 4 + N_2;"]);
+        let shader = process(source, &[("N", "8")]).unwrap();
+        assert_eq!(lines(&shader), ["let x = 8 + N_2;"]);
+    }
+
+    #[test]
+    fn chained_defines() {
+        let source = "#define A B
+#define B 1
+let x = A;";
+        for _ in 0..8 {
+            let shader = process(source, &[]).unwrap();
+            assert_eq!(lines(&shader), ["let x = 1;"]);
+        }
+        let shader = process("let x = A;", &[("B", "2"), ("A", "B + B")]).unwrap();
+        assert_eq!(lines(&shader), ["let x = 2 + 2;"]);
+        let e = process("#define A B\n#define B A\nA", &[]).unwrap_err();
+        assert_eq!(e.location.line, 3);
+        assert_eq!(e.message, "#define refers to itself");
+    }
+
+    #[test]
+    fn unbalanced_branches() {
+        let e = process("#ifdef A\n#else\n#else\n#endif", &[]).unwrap_err();
+        assert_eq!(e.location.line, 3);
+        assert_eq!(e.message, "#else after #else");
+        let e = process("a\n#else", &[]).unwrap_err();
+        assert_eq!(e.location.line, 2);
+        let e = process("#endif", &[]).unwrap_err();
+        assert_eq!(e.location.line, 1);
+        let e = process("a\n#ifdef A\nb", &[]).unwrap_err();
+        assert_eq!(e.location.line, 2);
+        assert_eq!(e.message, "#ifdef without #endif");
+    }
+
+    #[test]
+    fn include_cycle() {
+        let mut preprocessor = ShaderPreprocessor::new();
+        preprocessor.register_module("a", "#include b\na");
+        preprocessor.register_module("b", "#include a\nb");
+        let shader = preprocessor
+            .process("#include a\n#include b\nmain", "main.wgsl", &[])
+            .unwrap();
+        assert_eq!(lines(&shader), ["b", "a", "main"]);
+        assert!(shader.included_files().is_empty());
+    }
+
+    #[test]
+    fn unknown_module() {
+        let e = process("\n#include nope", &[]).unwrap_err();
+        assert_eq!(e.location.line, 2);
+        assert_eq!(e.message, "Unknown module nope");
+    }
+
+    #[test]
+    fn location_maps_to_source_line() {
+        let mut preprocessor = ShaderPreprocessor::new();
+        preprocessor.register_module("m", "// module\n#ifdef X\nx\n#endif\nm");
+        let shader = preprocessor
+            .process("first\n#include m\n\nlast", "main.wgsl", &[])
+            .unwrap();
+        assert_eq!(lines(&shader), ["first", "// module", "m", "", "last"]);
+        let at = |line| shader.location(line).map(|l| (l.file.as_str(), l.line));
+        assert_eq!(at(0), None);
+        assert_eq!(at(1), Some(("main.wgsl", 1)));
+        assert_eq!(at(2), Some(("m", 1)));
+        assert_eq!(at(3), Some(("m", 5)));
+        assert_eq!(at(4), Some(("main.wgsl", 3)));
+        assert_eq!(at(5), Some(("main.wgsl", 4)));
+        assert_eq!(at(6), None);
+    }
+
+    #[test]
+    fn validate_entry_points_and_capabilities() {
+        use naga::{valid::Capabilities, ShaderStage};
+
+        let shader = process(
+            "var<push_constant> c: f32;\n@vertex fn vs() -> @builtin(position) vec4<f32> { return vec4(c); }",
+            &[],
+        )
+        .unwrap();
+        let e = shader
+            .validate(&[("main", ShaderStage::Vertex)], Capabilities::all())
+            .unwrap_err();
+        assert!(e.location.is_none());
+        assert!(e.message.contains("`main`"));
+        assert!(shader
+            .validate(&[("vs", ShaderStage::Vertex)], Capabilities::PUSH_CONSTANT)
+            .is_ok());
+        assert!(shader
+            .validate(&[("vs", ShaderStage::Vertex)], Capabilities::empty())
+            .is_err());
+    }
+}
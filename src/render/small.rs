@@ -2,6 +2,7 @@ use std::ops::{Add, Mul};
 
 use glam::{Mat4, Quat, Vec2, Vec3, Vec4};
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Color {
     pub r: f32,
     pub g: f32,
@@ -9,6 +10,12 @@ pub struct Color {
     pub a: f32,
 }
 impl Color {
+    pub const WHITE: Color = Color {
+        r: 1.,
+        g: 1.,
+        b: 1.,
+        a: 1.,
+    };
     pub fn srgba(r: f32, g: f32, b: f32, a: f32) -> Self {
         Self { r, g, b, a }
     }
@@ -31,6 +38,9 @@ impl Color {
     pub fn srgb(r: f32, g: f32, b: f32) -> Self {
         Self { r, g, b, a: 1.0 }
     }
+    pub fn to_array(&self) -> [f32; 4] {
+        [self.r, self.g, self.b, self.a]
+    }
 }
 
 impl From<Color> for wgpu::Color {
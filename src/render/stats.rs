@@ -0,0 +1,375 @@
+//! Per frame statistics: counters of `Render`, bytes written to GPU, live resources
+//! and GPU time of render passes. See `Renderer::render_stats`
+
+use std::{
+    collections::VecDeque,
+    hash::{Hash, Hasher},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use wgpu::{Features, PipelineStatisticsTypes, QuerySet, QueryType, RenderPass};
+
+use super::context::RenderContext;
+
+/// Statistics of last frame
+#[derive(Clone, Debug, Default)]
+pub struct RenderStats {
+    pub draw_calls: u32,
+    /// Pipelines set on render pass, same pipeline again is not counted
+    pub pipeline_switches: u32,
+    pub bind_group_switches: u32,
+    /// Bytes and live resources are of context of renderer only
+    pub buffer_bytes_written: u64,
+    pub texture_bytes_written: u64,
+    pub live_buffers: u64,
+    pub live_textures: u64,
+    /// GPU time of every render pass. Results are from some frames ago,
+    /// empty if `Features::TIMESTAMP_QUERY` is not supported
+    pub gpu_passes: Vec<PassTime>,
+    /// Sum for all passes, `None` if `Features::PIPELINE_STATISTICS_QUERY` is not supported
+    pub pipeline_statistics: Option<PipelineStatistics>,
+}
+impl RenderStats {
+    /// GPU time of all passes
+    pub fn gpu_time(&self) -> Duration {
+        self.gpu_passes.iter().map(|p| p.time).sum()
+    }
+    /// Adds draw and switch counters of one pass
+    pub(crate) fn add_pass(&mut self, pass: &RenderStats) {
+        self.draw_calls += pass.draw_calls;
+        self.pipeline_switches += pass.pipeline_switches;
+        self.bind_group_switches += pass.bind_group_switches;
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PassTime {
+    pub label: String,
+    pub time: Duration,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PipelineStatistics {
+    pub vertex_shader_invocations: u64,
+    pub clipper_invocations: u64,
+    pub clipper_primitives_out: u64,
+    pub fragment_shader_invocations: u64,
+}
+
+/// Counters of one `RenderContext`, changed by its resources
+#[derive(Default)]
+pub(crate) struct Counters {
+    buffer_bytes: AtomicU64,
+    texture_bytes: AtomicU64,
+    live_buffers: AtomicU64,
+    live_textures: AtomicU64,
+}
+impl Counters {
+    pub(crate) fn buffer_written(&self, bytes: usize) {
+        self.buffer_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+    pub(crate) fn texture_written(&self, bytes: usize) {
+        self.texture_bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+    /// Takes bytes counters and reads live resources into `stats`
+    pub(crate) fn take(&self, stats: &mut RenderStats) {
+        stats.buffer_bytes_written = self.buffer_bytes.swap(0, Ordering::Relaxed);
+        stats.texture_bytes_written = self.texture_bytes.swap(0, Ordering::Relaxed);
+        stats.live_buffers = self.live_buffers.load(Ordering::Relaxed);
+        stats.live_textures = self.live_textures.load(Ordering::Relaxed);
+    }
+}
+
+/// Resource is counted as live in counters of its context while any clone of it exists.
+/// It is not part of resource identity: all are equal
+#[derive(Clone)]
+pub(crate) struct Live(#[allow(dead_code)] Arc<LiveCounter>);
+struct LiveCounter {
+    counters: Arc<Counters>,
+    kind: LiveKind,
+}
+enum LiveKind {
+    Buffer,
+    Texture,
+}
+impl Live {
+    pub(crate) fn buffer(context: &RenderContext) -> Self {
+        Self::new(context, LiveKind::Buffer)
+    }
+    pub(crate) fn texture(context: &RenderContext) -> Self {
+        Self::new(context, LiveKind::Texture)
+    }
+    fn new(context: &RenderContext, kind: LiveKind) -> Self {
+        let counter = LiveCounter {
+            counters: context.counters.clone(),
+            kind,
+        };
+        counter.count().fetch_add(1, Ordering::Relaxed);
+        Self(Arc::new(counter))
+    }
+}
+impl LiveCounter {
+    fn count(&self) -> &AtomicU64 {
+        match self.kind {
+            LiveKind::Buffer => &self.counters.live_buffers,
+            LiveKind::Texture => &self.counters.live_textures,
+        }
+    }
+}
+impl Drop for LiveCounter {
+    fn drop(&mut self) {
+        self.count().fetch_sub(1, Ordering::Relaxed);
+    }
+}
+impl PartialEq for Live {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+impl Eq for Live {}
+impl Hash for Live {
+    fn hash<H: Hasher>(&self, _: &mut H) {}
+}
+
+/// Passes which are measured in one frame
+const MAX_PASSES: u32 = 32;
+/// If results of this many frames are not read, next frame is not measured
+const MAX_FRAMES_IN_FLIGHT: usize = 3;
+const TIMESTAMPS_SIZE: u64 = MAX_PASSES as u64 * 2 * 8;
+/// Values of `PipelineStatistics`
+const STATISTICS_VALUES: u64 = 4;
+const STATISTICS_SIZE: u64 = MAX_PASSES as u64 * STATISTICS_VALUES * 8;
+
+/// Timestamp and pipeline statistics queries of render passes.
+/// Results are read without waiting when GPU finished frame
+pub(crate) struct GpuProfiler {
+    context: RenderContext,
+    current: Option<FrameQueries>,
+    in_flight: VecDeque<FrameQueries>,
+    free: Vec<FrameQueries>,
+    passes: Vec<PassTime>,
+    pipeline_statistics: Option<PipelineStatistics>,
+}
+
+struct FrameQueries {
+    timestamps: Option<QuerySet>,
+    statistics: Option<QuerySet>,
+    resolve: wgpu::Buffer,
+    readback: wgpu::Buffer,
+    labels: Vec<String>,
+    mapped: Arc<AtomicBool>,
+}
+
+/// Queries of one render pass
+#[derive(Default)]
+pub(crate) struct PassQueries {
+    timestamps: Option<(QuerySet, u32)>,
+    statistics: Option<(QuerySet, u32)>,
+}
+impl PassQueries {
+    /// For `RenderPassDescriptor`
+    pub(crate) fn timestamp_writes(&self) -> Option<wgpu::RenderPassTimestampWrites<'_>> {
+        self.timestamps
+            .as_ref()
+            .map(|(query_set, index)| wgpu::RenderPassTimestampWrites {
+                query_set,
+                beginning_of_pass_write_index: Some(index * 2),
+                end_of_pass_write_index: Some(index * 2 + 1),
+            })
+    }
+    /// Call after pass is began
+    pub(crate) fn begin(&self, pass: &mut RenderPass) {
+        if let Some((query_set, index)) = &self.statistics {
+            pass.begin_pipeline_statistics_query(query_set, *index);
+        }
+    }
+    /// Call before pass is ended
+    pub(crate) fn end(&self, pass: &mut RenderPass) {
+        if self.statistics.is_some() {
+            pass.end_pipeline_statistics_query();
+        }
+    }
+}
+
+impl GpuProfiler {
+    pub(crate) fn new(context: RenderContext) -> Self {
+        Self {
+            context,
+            current: None,
+            in_flight: VecDeque::new(),
+            free: Vec::new(),
+            passes: Vec::new(),
+            pipeline_statistics: None,
+        }
+    }
+    /// Empty if device doesn't support queries or too many passes are measured
+    pub(crate) fn begin_pass(&mut self, label: &str) -> PassQueries {
+        let features = self.context.features();
+        if !features.intersects(Features::TIMESTAMP_QUERY | Features::PIPELINE_STATISTICS_QUERY) {
+            return PassQueries::default();
+        }
+        if self.current.is_none() {
+            if self.free.is_empty() && self.in_flight.len() >= MAX_FRAMES_IN_FLIGHT {
+                return PassQueries::default();
+            }
+            let frame = match self.free.pop() {
+                Some(frame) => frame,
+                None => FrameQueries::new(&self.context),
+            };
+            self.current = Some(frame);
+        }
+        let frame = self.current.as_mut().unwrap();
+        if frame.labels.len() as u32 >= MAX_PASSES {
+            return PassQueries::default();
+        }
+        let index = frame.labels.len() as u32;
+        frame.labels.push(label.to_string());
+        PassQueries {
+            timestamps: frame.timestamps.clone().map(|q| (q, index)),
+            statistics: frame.statistics.clone().map(|q| (q, index)),
+        }
+    }
+    /// Reads finished frames and sends queries of this frame for reading
+    pub(crate) fn end_frame(&mut self, stats: &mut RenderStats) {
+        let renderer = self.context.clone();
+        if !self.in_flight.is_empty() {
+            let _ = renderer.device().poll(wgpu::Maintain::Poll);
+        }
+        while self
+            .in_flight
+            .front()
+            .is_some_and(|f| f.mapped.load(Ordering::Acquire))
+        {
+            let mut frame = self.in_flight.pop_front().unwrap();
+            self.read(&frame, renderer.queue().get_timestamp_period());
+            frame.readback.unmap();
+            frame.mapped.store(false, Ordering::Release);
+            frame.labels.clear();
+            self.free.push(frame);
+        }
+        stats.gpu_passes = self.passes.clone();
+        stats.pipeline_statistics = self.pipeline_statistics;
+
+        let Some(frame) = self.current.take() else {
+            return;
+        };
+        let count = frame.labels.len() as u32;
+        let mut encoder =
+            renderer
+                .device()
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Resolve queries"),
+                });
+        if let Some(query_set) = &frame.timestamps {
+            encoder.resolve_query_set(query_set, 0..count * 2, &frame.resolve, 0);
+        }
+        if let Some(query_set) = &frame.statistics {
+            encoder.resolve_query_set(query_set, 0..count, &frame.resolve, TIMESTAMPS_SIZE);
+        }
+        encoder.copy_buffer_to_buffer(
+            &frame.resolve,
+            0,
+            &frame.readback,
+            0,
+            TIMESTAMPS_SIZE + STATISTICS_SIZE,
+        );
+        renderer.queue().submit(std::iter::once(encoder.finish()));
+        let mapped = frame.mapped.clone();
+        frame
+            .readback
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                if result.is_ok() {
+                    mapped.store(true, Ordering::Release);
+                }
+            });
+        self.in_flight.push_back(frame);
+    }
+    fn read(&mut self, frame: &FrameQueries, period: f32) {
+        let data = frame.readback.slice(..).get_mapped_range();
+        let values: &[u64] = bytemuck::cast_slice(&data);
+        if frame.timestamps.is_some() {
+            self.passes = frame
+                .labels
+                .iter()
+                .enumerate()
+                .map(|(i, label)| {
+                    let ticks = values[i * 2 + 1].saturating_sub(values[i * 2]);
+                    PassTime {
+                        label: label.clone(),
+                        time: Duration::from_nanos((ticks as f64 * period as f64) as u64),
+                    }
+                })
+                .collect();
+        }
+        if frame.statistics.is_some() {
+            let start = (TIMESTAMPS_SIZE / 8) as usize;
+            let mut sum = PipelineStatistics::default();
+            for pass in values[start..]
+                .chunks(STATISTICS_VALUES as usize)
+                .take(frame.labels.len())
+            {
+                sum.vertex_shader_invocations += pass[0];
+                sum.clipper_invocations += pass[1];
+                sum.clipper_primitives_out += pass[2];
+                sum.fragment_shader_invocations += pass[3];
+            }
+            self.pipeline_statistics = Some(sum);
+        }
+    }
+}
+
+impl FrameQueries {
+    fn new(context: &RenderContext) -> Self {
+        let features = context.features();
+        let device = context.device();
+        let timestamps = features.contains(Features::TIMESTAMP_QUERY).then(|| {
+            device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("Pass timestamps"),
+                ty: QueryType::Timestamp,
+                count: MAX_PASSES * 2,
+            })
+        });
+        let statistics = features
+            .contains(Features::PIPELINE_STATISTICS_QUERY)
+            .then(|| {
+                device.create_query_set(&wgpu::QuerySetDescriptor {
+                    label: Some("Pass statistics"),
+                    ty: QueryType::PipelineStatistics(
+                        PipelineStatisticsTypes::VERTEX_SHADER_INVOCATIONS
+                            | PipelineStatisticsTypes::CLIPPER_INVOCATIONS
+                            | PipelineStatisticsTypes::CLIPPER_PRIMITIVES_OUT
+                            | PipelineStatisticsTypes::FRAGMENT_SHADER_INVOCATIONS,
+                    ),
+                    count: MAX_PASSES,
+                })
+            });
+        let buffer = |label, usage| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: TIMESTAMPS_SIZE + STATISTICS_SIZE,
+                usage,
+                mapped_at_creation: false,
+            })
+        };
+        Self {
+            timestamps,
+            statistics,
+            resolve: buffer(
+                "Query resolve",
+                wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            ),
+            readback: buffer(
+                "Query readback",
+                wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            ),
+            labels: Vec::new(),
+            mapped: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
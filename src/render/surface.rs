@@ -1,7 +1,4 @@
-use std::{
-    collections::HashMap,
-    sync::{Arc, LazyLock, Mutex, MutexGuard},
-};
+use std::{collections::HashMap, sync::Arc};
 
 use wgpu::{Surface, SurfaceConfiguration};
 use winit::{
@@ -9,8 +6,9 @@ use winit::{
     window::{Window, WindowId},
 };
 
-use super::UnMutRenderer;
+use super::context::RenderContext;
 
+/// Surfaces of one `RenderContext`, see `RenderContext::surfaces`
 pub(crate) struct Surfaces<'a> {
     surfaces: HashMap<SurfaceId, CatSurface<'a>>,
     window_surfaces: HashMap<WindowId, SurfaceId>,
@@ -21,6 +19,8 @@ pub struct CatSurface<'a> {
     pub(crate) wgpu_surface: Arc<Surface<'a>>,
     pub(crate) size: PhysicalSize<u32>,
     pub(crate) config: SurfaceConfiguration,
+    /// For creating surface again on new device
+    window: Arc<Window>,
 }
 
 impl<'a> Surfaces<'a> {
@@ -57,18 +57,22 @@ impl<'a> Surfaces<'a> {
     pub(crate) fn get_mut_surface(&mut self, id: SurfaceId) -> &mut CatSurface<'a> {
         self.surfaces.get_mut(&id).unwrap()
     }
-    pub(crate) fn create_surface(&mut self, window: Arc<Window>) -> SurfaceId {
-        let surface = UnMutRenderer::get()
+    pub(crate) fn create_surface(
+        &mut self,
+        context: &RenderContext,
+        window: Arc<Window>,
+    ) -> SurfaceId {
+        let surface = context
             .instance
             .create_surface(window.clone())
             .expect("Failed to create surface");
-        if !UnMutRenderer::get().adapter.is_surface_supported(&surface) {
+        if !context.adapter().is_surface_supported(&surface) {
             // WebGl2 requirement TODO
             todo!()
         }
         let size = window.inner_size();
 
-        let surface_caps = surface.get_capabilities(&UnMutRenderer::get().adapter);
+        let surface_caps = surface.get_capabilities(context.adapter());
         // sRGB
         let surface_format = surface_caps
             .formats
@@ -94,31 +98,55 @@ impl<'a> Surfaces<'a> {
                 wgpu_surface: Arc::new(surface),
                 size,
                 config,
+                window,
             },
         );
         self.last_id += 1;
         SurfaceId(self.last_id - 1)
     }
-    pub(crate) fn get() -> MutexGuard<'a, Surfaces<'static>> {
-        SURFACES.lock().unwrap()
+    /// Creates every surface again on `new` context, ids are same.
+    /// Format is kept if new adapter supports it
+    pub(crate) fn recreate_all(&mut self, new: &RenderContext) -> anyhow::Result<()> {
+        for surface in self.surfaces.values_mut() {
+            let wgpu_surface = new.instance.create_surface(surface.window.clone())?;
+            let caps = wgpu_surface.get_capabilities(new.adapter());
+            if caps.formats.is_empty() {
+                anyhow::bail!("Surface is not supported by new adapter");
+            }
+            if !caps.formats.contains(&surface.config.format) {
+                surface.config.format = caps.formats[0];
+            }
+            if surface.size.width != 0 && surface.size.height != 0 {
+                wgpu_surface.configure(new.device(), &surface.config);
+            }
+            surface.wgpu_surface = Arc::new(wgpu_surface);
+        }
+        Ok(())
+    }
+    /// Configures surface again, for `SurfaceError::Lost` and `Outdated`
+    pub(crate) fn reconfigure(&mut self, device: &wgpu::Device, id: SurfaceId) {
+        let surface = self.get_mut_surface(id);
+        if surface.size.width != 0 && surface.size.height != 0 {
+            surface.wgpu_surface.configure(device, &surface.config);
+        }
     }
-    pub(crate) fn resize_window_surface(&mut self, window: &WindowId, new_size: PhysicalSize<u32>) {
+    pub(crate) fn resize_window_surface(
+        &mut self,
+        device: &wgpu::Device,
+        window: &WindowId,
+        new_size: PhysicalSize<u32>,
+    ) {
         if let Some(surface) = self.window_surfaces.get(window) {
             if new_size.width != 0 && new_size.height != 0 {
                 let surface = self.get_mut_surface(surface.clone());
                 surface.size = new_size;
                 surface.config.width = new_size.width;
                 surface.config.height = new_size.height;
-                surface
-                    .wgpu_surface
-                    .configure(&UnMutRenderer::get().device, &surface.config);
+                surface.wgpu_surface.configure(device, &surface.config);
             }
         }
     }
 }
 
-pub(crate) static SURFACES: LazyLock<Arc<Mutex<Surfaces>>> =
-    std::sync::LazyLock::new(|| Arc::new(Mutex::new(Surfaces::new())));
-
 #[derive(Hash, PartialEq, Eq, Clone, Debug, Default)]
 pub struct SurfaceId(u32);
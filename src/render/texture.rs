@@ -1,28 +1,777 @@
 //! `Texture` is need for render textures :)
+//! Clones of texture are same texture, `write_region`, `write_full` and `resize`
+//! change it for all clones, so `Material`s and `Sprite`s using it see changes.
+//! Besides 2D images there are texture arrays, 3D textures and cubemaps,
+//! see `Texture::view_dimension`
+
+use std::{
+    hash::{Hash, Hasher},
+    sync::{Arc, RwLock},
+};
 
 use anyhow::*;
-use glam::UVec2;
-use image::{DynamicImage, GenericImageView};
-use wgpu::{AddressMode, FilterMode};
+use glam::{UVec2, UVec3, Vec3};
+use image::{DynamicImage, GenericImageView, Rgba32FImage};
+use wgpu::{AddressMode, FilterMode, TextureUsages, TextureViewDimension};
 
 use super::{
-    surface::{SurfaceId, Surfaces},
-    UnMutRenderer,
+    context::RenderContext,
+    mipmap::{self, mip_level_count},
+    stats::Live,
+    surface::SurfaceId,
 };
 
+/// Region of texture in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct TextureRect {
+    pub origin: UVec2,
+    pub size: UVec2,
+}
+
+impl TextureRect {
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self {
+            origin: UVec2::new(x, y),
+            size: UVec2::new(width, height),
+        }
+    }
+}
+
+/// Format of texels, images are converted to it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum PixelFormat {
+    /// One channel, luma of image
+    R8,
+    /// Luma and alpha of image
+    Rg8,
+    /// Linear, for normal maps and data textures
+    Rgba8,
+    /// Color textures
+    #[default]
+    Rgba8Srgb,
+    /// HDR images
+    Rgba16Float,
+    /// HDR images without precision loss, not filterable
+    Rgba32Float,
+    /// Integer data: luma of image without normalization, not filterable
+    R32Uint,
+}
+
+impl PixelFormat {
+    pub fn wgpu(self) -> wgpu::TextureFormat {
+        match self {
+            PixelFormat::R8 => wgpu::TextureFormat::R8Unorm,
+            PixelFormat::Rg8 => wgpu::TextureFormat::Rg8Unorm,
+            PixelFormat::Rgba8 => wgpu::TextureFormat::Rgba8Unorm,
+            PixelFormat::Rgba8Srgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+            PixelFormat::Rgba16Float => wgpu::TextureFormat::Rgba16Float,
+            PixelFormat::Rgba32Float => wgpu::TextureFormat::Rgba32Float,
+            PixelFormat::R32Uint => wgpu::TextureFormat::R32Uint,
+        }
+    }
+    /// None if format is not one of `PixelFormat`
+    pub fn from_wgpu(format: wgpu::TextureFormat) -> Option<Self> {
+        Some(match format {
+            wgpu::TextureFormat::R8Unorm => PixelFormat::R8,
+            wgpu::TextureFormat::Rg8Unorm => PixelFormat::Rg8,
+            wgpu::TextureFormat::Rgba8Unorm => PixelFormat::Rgba8,
+            wgpu::TextureFormat::Rgba8UnormSrgb => PixelFormat::Rgba8Srgb,
+            wgpu::TextureFormat::Rgba16Float => PixelFormat::Rgba16Float,
+            wgpu::TextureFormat::Rgba32Float => PixelFormat::Rgba32Float,
+            wgpu::TextureFormat::R32Uint => PixelFormat::R32Uint,
+            _ => return None,
+        })
+    }
+    pub fn bytes_per_pixel(self) -> u32 {
+        match self {
+            PixelFormat::R8 => 1,
+            PixelFormat::Rg8 => 2,
+            PixelFormat::Rgba8 | PixelFormat::Rgba8Srgb | PixelFormat::R32Uint => 4,
+            PixelFormat::Rgba16Float => 8,
+            PixelFormat::Rgba32Float => 16,
+        }
+    }
+    /// Linear filter can be used without extra features
+    pub fn is_filterable(self) -> bool {
+        !matches!(self, PixelFormat::Rgba32Float | PixelFormat::R32Uint)
+    }
+    /// Type for texture binding, see `MaterialLayoutBuilder::register_texture_with_sample_type_at`
+    pub fn sample_type(self) -> wgpu::TextureSampleType {
+        match self {
+            PixelFormat::R32Uint => wgpu::TextureSampleType::Uint,
+            format => wgpu::TextureSampleType::Float {
+                filterable: format.is_filterable(),
+            },
+        }
+    }
+    /// Pixels of image in this format, rows are tightly packed.
+    /// 16 bit and float images keep precision in float formats
+    pub fn convert(self, img: &DynamicImage) -> Vec<u8> {
+        match self {
+            PixelFormat::R8 => img.to_luma8().into_raw(),
+            PixelFormat::Rg8 => img.to_luma_alpha8().into_raw(),
+            PixelFormat::Rgba8 | PixelFormat::Rgba8Srgb => img.to_rgba8().into_raw(),
+            PixelFormat::Rgba16Float => img
+                .to_rgba32f()
+                .into_raw()
+                .into_iter()
+                .flat_map(|v| f32_to_f16(v).to_le_bytes())
+                .collect(),
+            PixelFormat::Rgba32Float => bytemuck::cast_slice(&img.to_rgba32f().into_raw()).to_vec(),
+            PixelFormat::R32Uint => {
+                let values: Vec<u32> = match img {
+                    DynamicImage::ImageLuma8(_)
+                    | DynamicImage::ImageLumaA8(_)
+                    | DynamicImage::ImageRgb8(_)
+                    | DynamicImage::ImageRgba8(_) => img
+                        .to_luma8()
+                        .into_raw()
+                        .into_iter()
+                        .map(u32::from)
+                        .collect(),
+                    _ => img
+                        .to_luma16()
+                        .into_raw()
+                        .into_iter()
+                        .map(u32::from)
+                        .collect(),
+                };
+                bytemuck::cast_slice(&values).to_vec()
+            }
+        }
+    }
+}
+
+/// f16 bits, rounds toward zero
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+    if exp == 0xff {
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+    let exp = exp - 127 + 15;
+    if exp >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if exp <= 0 {
+        // Subnormal
+        if exp < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x80_0000;
+        return sign | (mantissa >> (14 - exp)) as u16;
+    }
+    sign | ((exp as u16) << 10) | (mantissa >> 13) as u16
+}
+
+/// Settings of sampler, textures with same options share sampler.
+/// Change sampler of texture with `Texture::set_sampler`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SamplerOptions {
+    pub mag_filter: FilterMode,
+    pub min_filter: FilterMode,
+    /// Filter between mip levels
+    pub mipmap_filter: FilterMode,
+    pub address_mode_u: AddressMode,
+    pub address_mode_v: AddressMode,
+    pub address_mode_w: AddressMode,
+    pub lod_min_clamp: f32,
+    pub lod_max_clamp: f32,
+    /// 1 is off, ignored if some filter is not `FilterMode::Linear`
+    pub anisotropy_clamp: u16,
+    /// For depth textures
+    pub compare: Option<wgpu::CompareFunction>,
+    /// Color of `AddressMode::ClampToBorder`, which needs
+    /// `Features::ADDRESS_MODE_CLAMP_TO_BORDER`
+    pub border_color: Option<wgpu::SamplerBorderColor>,
+}
+
+impl Default for SamplerOptions {
+    fn default() -> Self {
+        Self {
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Linear,
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            lod_min_clamp: 0.0,
+            lod_max_clamp: 32.0,
+            anisotropy_clamp: 1,
+            compare: None,
+            border_color: None,
+        }
+    }
+}
+
+impl SamplerOptions {
+    /// Same filter for mag, min and mip
+    pub fn filter(mut self, filter: FilterMode) -> Self {
+        self.mag_filter = filter;
+        self.min_filter = filter;
+        self.mipmap_filter = filter;
+        self
+    }
+    /// Same address mode for all axes
+    pub fn address_mode(mut self, address_mode: AddressMode) -> Self {
+        self.address_mode_u = address_mode;
+        self.address_mode_v = address_mode;
+        self.address_mode_w = address_mode;
+        self
+    }
+    pub fn lod_clamp(mut self, min: f32, max: f32) -> Self {
+        self.lod_min_clamp = min;
+        self.lod_max_clamp = max;
+        self
+    }
+    pub fn anisotropy(mut self, clamp: u16) -> Self {
+        self.anisotropy_clamp = clamp;
+        self
+    }
+    pub fn compare(mut self, compare: wgpu::CompareFunction) -> Self {
+        self.compare = Some(compare);
+        self
+    }
+    pub fn border_color(mut self, color: wgpu::SamplerBorderColor) -> Self {
+        self.border_color = Some(color);
+        self
+    }
+    /// Filters are `Nearest` if texture is not `filterable`, options which device
+    /// can't use are replaced with warning
+    fn create(&self, context: &RenderContext, filterable: bool) -> Arc<wgpu::Sampler> {
+        let filter = |filter| {
+            if filterable {
+                filter
+            } else {
+                FilterMode::Nearest
+            }
+        };
+        let (mag_filter, min_filter, mipmap_filter) = (
+            filter(self.mag_filter),
+            filter(self.min_filter),
+            filter(self.mipmap_filter),
+        );
+        let all_linear = [mag_filter, min_filter, mipmap_filter] == [FilterMode::Linear; 3];
+        if self.anisotropy_clamp > 1 && !all_linear {
+            log::warn!("Anisotropy needs linear filters, it is off");
+        }
+        let has_border = context
+            .features()
+            .contains(wgpu::Features::ADDRESS_MODE_CLAMP_TO_BORDER);
+        let address = |mode| {
+            if mode == AddressMode::ClampToBorder && !has_border {
+                log::warn!(
+                    "Device doesn't support `AddressMode::ClampToBorder`, `ClampToEdge` is used"
+                );
+                AddressMode::ClampToEdge
+            } else {
+                mode
+            }
+        };
+        context.cache.sampler(
+            context.device(),
+            &wgpu::SamplerDescriptor {
+                label: None,
+                address_mode_u: address(self.address_mode_u),
+                address_mode_v: address(self.address_mode_v),
+                address_mode_w: address(self.address_mode_w),
+                mag_filter,
+                min_filter,
+                mipmap_filter,
+                lod_min_clamp: self.lod_min_clamp,
+                lod_max_clamp: self.lod_max_clamp,
+                compare: self.compare,
+                anisotropy_clamp: if all_linear {
+                    self.anisotropy_clamp.max(1)
+                } else {
+                    1
+                },
+                border_color: self.border_color,
+            },
+        )
+    }
+}
+
+/// Descriptor of texture, set fields or use methods:
+/// `TextureOptions::default().format(PixelFormat::Rgba16Float).label("sky")`
+#[derive(Debug, Clone)]
+pub struct TextureOptions {
+    pub format: PixelFormat,
+    /// `TEXTURE_BINDING` and `COPY_DST` are always added.
+    /// Add `COPY_SRC` to keep content on `Texture::resize`
+    pub usage: TextureUsages,
+    /// Not filterable formats use `FilterMode::Nearest`
+    pub sampler: SamplerOptions,
+    pub label: Option<String>,
+    /// Create mip chain down to 1x1, texture gets `RENDER_ATTACHMENT` usage
+    /// if levels are made on GPU
+    pub mipmaps: bool,
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        Self {
+            format: PixelFormat::Rgba8Srgb,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            sampler: SamplerOptions::default(),
+            label: None,
+            mipmaps: false,
+        }
+    }
+}
+
+impl TextureOptions {
+    pub fn format(mut self, format: PixelFormat) -> Self {
+        self.format = format;
+        self
+    }
+    pub fn usage(mut self, usage: TextureUsages) -> Self {
+        self.usage = usage;
+        self
+    }
+    pub fn sampler(mut self, sampler: SamplerOptions) -> Self {
+        self.sampler = sampler;
+        self
+    }
+    /// See `SamplerOptions::filter`
+    pub fn filter(mut self, filter: FilterMode) -> Self {
+        self.sampler = self.sampler.filter(filter);
+        self
+    }
+    /// See `SamplerOptions::address_mode`
+    pub fn address_mode(mut self, address_mode: AddressMode) -> Self {
+        self.sampler = self.sampler.address_mode(address_mode);
+        self
+    }
+    pub fn label(mut self, label: &str) -> Self {
+        self.label = Some(label.to_string());
+        self
+    }
+    pub fn mipmaps(mut self, mipmaps: bool) -> Self {
+        self.mipmaps = mipmaps;
+        self
+    }
+    fn wgpu_usage(&self) -> TextureUsages {
+        let usage = self.usage | TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST;
+        if self.mipmaps && mipmap::is_gpu_supported(self.format.wgpu()) {
+            usage | TextureUsages::RENDER_ATTACHMENT
+        } else {
+            usage
+        }
+    }
+}
+
+struct TextureInner {
+    texture: wgpu::Texture,
+    /// Kept for texture made by `resize`
+    label: Option<String>,
+    view: wgpu::TextureView,
+    view_dimension: TextureViewDimension,
+    sampler: Arc<wgpu::Sampler>,
+    sampler_options: SamplerOptions,
+    /// Changed on resize and sampler change, bind groups with old ones are rebuilt
+    generation: u64,
+    /// For eviction from cache on drop
+    context: RenderContext,
+}
+
+impl Drop for TextureInner {
+    fn drop(&mut self) {
+        self.context.cache.evict_view(&self.view);
+    }
+}
+
 #[derive(Clone)]
 pub struct Texture {
-    #[allow(unused)]
-    pub(crate) texture: wgpu::Texture,
-    pub view: wgpu::TextureView,
-    pub sampler: wgpu::Sampler,
+    inner: Arc<RwLock<TextureInner>>,
+    context: RenderContext,
+    _live: Live,
+}
+
+/// Same texture
+impl PartialEq for Texture {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+impl Eq for Texture {}
+impl Hash for Texture {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.inner).hash(state);
+    }
 }
 
 impl Texture {
+    fn from_parts(
+        context: &RenderContext,
+        texture: wgpu::Texture,
+        label: Option<String>,
+        view_dimension: TextureViewDimension,
+        sampler_options: SamplerOptions,
+    ) -> Self {
+        let filterable = PixelFormat::from_wgpu(texture.format()).is_none_or(|f| f.is_filterable());
+        let sampler = sampler_options.create(context, filterable);
+        let view = create_view(&texture, view_dimension);
+        Self {
+            inner: Arc::new(RwLock::new(TextureInner {
+                texture,
+                label,
+                view,
+                view_dimension,
+                sampler,
+                sampler_options,
+                generation: 0,
+                context: context.clone(),
+            })),
+            context: context.clone(),
+            _live: Live::texture(context),
+        }
+    }
     pub fn get_size(&self) -> UVec2 {
-        let size_3d = self.texture.size();
+        let size_3d = self.inner.read().unwrap().texture.size();
         UVec2::new(size_3d.width, size_3d.height)
     }
+    /// Current view, it is other one after `resize`
+    pub fn view(&self) -> wgpu::TextureView {
+        self.inner.read().unwrap().view.clone()
+    }
+    /// Current sampler, it is other one after `set_sampler`
+    pub fn sampler(&self) -> wgpu::Sampler {
+        (*self.inner.read().unwrap().sampler).clone()
+    }
+    pub fn sampler_options(&self) -> SamplerOptions {
+        self.inner.read().unwrap().sampler_options
+    }
+    /// Changes sampler for all clones of texture, content is not uploaded again
+    pub fn set_sampler(&self, options: SamplerOptions) {
+        let mut inner = self.inner.write().unwrap();
+        if inner.sampler_options == options {
+            return;
+        }
+        let filterable =
+            PixelFormat::from_wgpu(inner.texture.format()).is_none_or(|f| f.is_filterable());
+        inner.sampler = options.create(&self.context, filterable);
+        inner.sampler_options = options;
+        inner.generation += 1;
+    }
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.inner.read().unwrap().texture.format()
+    }
+    pub fn usage(&self) -> TextureUsages {
+        self.inner.read().unwrap().texture.usage()
+    }
+    /// Current wgpu texture, it is other one after `resize`
+    pub fn wgpu_texture(&self) -> wgpu::Texture {
+        self.inner.read().unwrap().texture.clone()
+    }
+    /// `D2Array`, `D3` or `Cube` for textures made with `array_from_images`,
+    /// `volume_from_*` and `cubemap_from_*`
+    pub fn view_dimension(&self) -> TextureViewDimension {
+        self.inner.read().unwrap().view_dimension
+    }
+    /// Number of layers, depth of 3D texture
+    pub fn layer_count(&self) -> u32 {
+        self.inner.read().unwrap().texture.depth_or_array_layers()
+    }
+    /// 1 if texture has no mip chain
+    pub fn mip_level_count(&self) -> u32 {
+        self.inner.read().unwrap().texture.mip_level_count()
+    }
+    pub(crate) fn generation(&self) -> u64 {
+        self.inner.read().unwrap().generation
+    }
+    /// Context on which texture is created
+    pub fn context(&self) -> &RenderContext {
+        &self.context
+    }
+
+    /// Empty texture in `Rgba8UnormSrgb`, `COPY_DST` and `TEXTURE_BINDING` are added to usage.
+    /// Add `COPY_SRC` to keep content on `resize`
+    pub fn empty(size: UVec2, usage: TextureUsages, filter: FilterMode) -> Self {
+        Self::empty_in(&RenderContext::global(), size, usage, filter)
+    }
+    /// See `empty`
+    pub fn empty_in(
+        context: &RenderContext,
+        size: UVec2,
+        usage: TextureUsages,
+        filter: FilterMode,
+    ) -> Self {
+        Self::empty_with_options_in(
+            context,
+            size,
+            &TextureOptions::default().usage(usage).filter(filter),
+        )
+    }
+    /// Empty texture with format, usage and sampler of `options`
+    pub fn empty_with_options(size: UVec2, options: &TextureOptions) -> Self {
+        Self::empty_with_options_in(&RenderContext::global(), size, options)
+    }
+    /// See `empty_with_options`
+    pub fn empty_with_options_in(
+        context: &RenderContext,
+        size: UVec2,
+        options: &TextureOptions,
+    ) -> Self {
+        Self::empty_layers_in(context, size, 1, TextureViewDimension::D2, options)
+    }
+    /// Mip chain is not made for 3D textures
+    fn empty_layers_in(
+        context: &RenderContext,
+        size: UVec2,
+        layers: u32,
+        view_dimension: TextureViewDimension,
+        options: &TextureOptions,
+    ) -> Self {
+        let size = size.max(UVec2::ONE);
+        let is_volume = view_dimension == TextureViewDimension::D3;
+        if is_volume && options.mipmaps {
+            log::warn!("Mipmaps of 3D textures are not supported");
+        }
+        let mip_levels = if options.mipmaps && !is_volume {
+            mip_level_count(size)
+        } else {
+            1
+        };
+        let texture = create_texture(
+            context,
+            options.label.as_deref(),
+            wgpu::Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: layers.max(1),
+            },
+            view_dimension,
+            mip_levels,
+            options.format.wgpu(),
+            options.wgpu_usage(),
+        );
+        Self::from_parts(
+            context,
+            texture,
+            options.label.clone(),
+            view_dimension,
+            options.sampler,
+        )
+    }
+
+    /// Writes pixels into `rect`, rows are tightly packed in format of texture.
+    /// Texture needs `COPY_DST` usage. Mip chain is not changed, see `generate_mipmaps`
+    pub fn write_region(&self, rect: TextureRect, pixels: &[u8]) -> Result<()> {
+        self.write_level(0, 0, rect, pixels)
+    }
+    /// `write_region` for layer of array or cubemap, slice of 3D texture
+    pub fn write_layer(&self, layer: u32, rect: TextureRect, pixels: &[u8]) -> Result<()> {
+        self.write_level(0, layer, rect, pixels)
+    }
+    fn write_level(&self, level: u32, layer: u32, rect: TextureRect, pixels: &[u8]) -> Result<()> {
+        let inner = self.inner.read().unwrap();
+        let texture = &inner.texture;
+        let size = texture.size().mip_level_size(level, texture.dimension());
+        if layer >= size.depth_or_array_layers {
+            bail!(
+                "Layer {} is out of texture with {} layers",
+                layer,
+                size.depth_or_array_layers
+            );
+        }
+        let size = UVec2::new(size.width, size.height);
+        let end = rect
+            .origin
+            .x
+            .checked_add(rect.size.x)
+            .zip(rect.origin.y.checked_add(rect.size.y));
+        let Some((end_x, end_y)) = end else {
+            bail!("Region {:?} is too large", rect);
+        };
+        if end_x > size.x || end_y > size.y {
+            bail!(
+                "Region {:?} is out of texture with size {}x{}",
+                rect,
+                size.x,
+                size.y
+            );
+        }
+        if !texture.usage().contains(TextureUsages::COPY_DST) {
+            bail!("Texture is not created with `TextureUsages::COPY_DST`");
+        }
+        let Some(block_size) = texture.format().block_copy_size(None) else {
+            bail!("Can't write into texture of format {:?}", texture.format());
+        };
+        let bytes_per_row = rect.size.x * block_size;
+        let expected = bytes_per_row as usize * rect.size.y as usize;
+        if pixels.len() != expected {
+            bail!(
+                "Region {}x{} needs {} bytes, given {}",
+                rect.size.x,
+                rect.size.y,
+                expected,
+                pixels.len()
+            );
+        }
+        if expected == 0 {
+            return Ok(());
+        }
+        self.context.counters.texture_written(pixels.len());
+        self.context.queue().write_texture(
+            wgpu::TexelCopyTextureInfo {
+                aspect: wgpu::TextureAspect::All,
+                texture,
+                mip_level: level,
+                origin: wgpu::Origin3d {
+                    x: rect.origin.x,
+                    y: rect.origin.y,
+                    z: layer,
+                },
+            },
+            pixels,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(bytes_per_row),
+                rows_per_image: Some(rect.size.y),
+            },
+            wgpu::Extent3d {
+                width: rect.size.x,
+                height: rect.size.y,
+                depth_or_array_layers: 1,
+            },
+        );
+        Ok(())
+    }
+    /// Replaces content with image converted to format of texture,
+    /// texture is resized if image has other size. Only for textures with one layer
+    pub fn write_full(&self, img: &DynamicImage) -> Result<()> {
+        if self.layer_count() > 1 {
+            bail!("Texture has few layers, use `write_layer`");
+        }
+        let format = self.format();
+        let Some(pixel_format) = PixelFormat::from_wgpu(format) else {
+            bail!("Can't convert image to texture format {:?}", format);
+        };
+        let (width, height) = img.dimensions();
+        let size = UVec2::new(width, height);
+        if size != self.get_size() {
+            self.resize(size);
+        }
+        self.write_region(
+            TextureRect::new(0, 0, width, height),
+            &pixel_format.convert(img),
+        )?;
+        self.fill_mipmaps(Some(std::slice::from_ref(img)))
+    }
+    /// Makes levels of mip chain from first one on GPU.
+    /// Error if texture is not created with `TextureOptions::mipmaps` or format can't be filtered
+    pub fn generate_mipmaps(&self) -> Result<()> {
+        self.fill_mipmaps(None)
+    }
+    /// On GPU if it can, else from `images` (one for every layer) on CPU
+    fn fill_mipmaps(&self, images: Option<&[DynamicImage]>) -> Result<()> {
+        let texture = self.wgpu_texture();
+        if texture.mip_level_count() == 1 {
+            return Ok(());
+        }
+        if can_generate_mipmaps(&texture) {
+            self.context
+                .mipmaps
+                .generate(self.context.device(), self.context.queue(), &texture);
+            return Ok(());
+        }
+        let Some(images) = images else {
+            bail!(
+                "Mipmaps of format {:?} can't be made on GPU, use `write_full`",
+                texture.format()
+            );
+        };
+        let Some(format) = PixelFormat::from_wgpu(texture.format()) else {
+            bail!(
+                "Can't convert image to texture format {:?}",
+                texture.format()
+            );
+        };
+        // Integer data is not averaged
+        let filter = if format == PixelFormat::R32Uint {
+            image::imageops::FilterType::Nearest
+        } else {
+            image::imageops::FilterType::Triangle
+        };
+        for (layer, img) in images.iter().enumerate() {
+            for level in 1..texture.mip_level_count() {
+                let size = texture.size().mip_level_size(level, texture.dimension());
+                let level_img = img.resize_exact(size.width, size.height, filter);
+                self.write_level(
+                    level,
+                    layer as u32,
+                    TextureRect::new(0, 0, size.width, size.height),
+                    &format.convert(&level_img),
+                )?;
+            }
+        }
+        Ok(())
+    }
+    /// New size with same format and usage, handle stays same so `Material`s
+    /// using texture pick it up. Content of overlapping area is kept if texture has
+    /// `COPY_SRC` usage, other is empty. Mip chain is made again on GPU if it can
+    pub fn resize(&self, size: UVec2) {
+        let size = size.max(UVec2::ONE);
+        let mut inner = self.inner.write().unwrap();
+        let old = &inner.texture;
+        if size == UVec2::new(old.width(), old.height()) {
+            return;
+        }
+        let mip_levels = if old.mip_level_count() > 1 {
+            mip_level_count(size)
+        } else {
+            1
+        };
+        let texture = create_texture(
+            &self.context,
+            inner.label.as_deref(),
+            wgpu::Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: old.depth_or_array_layers(),
+            },
+            inner.view_dimension,
+            mip_levels,
+            old.format(),
+            old.usage(),
+        );
+        if old
+            .usage()
+            .contains(TextureUsages::COPY_SRC | TextureUsages::COPY_DST)
+        {
+            let mut encoder =
+                self.context
+                    .device()
+                    .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                        label: Some("Texture resize"),
+                    });
+            encoder.copy_texture_to_texture(
+                old.as_image_copy(),
+                texture.as_image_copy(),
+                wgpu::Extent3d {
+                    width: size.x.min(old.width()),
+                    height: size.y.min(old.height()),
+                    depth_or_array_layers: old.depth_or_array_layers(),
+                },
+            );
+            self.context.queue().submit([encoder.finish()]);
+            if mip_levels > 1 && can_generate_mipmaps(&texture) {
+                self.context.mipmaps.generate(
+                    self.context.device(),
+                    self.context.queue(),
+                    &texture,
+                );
+            }
+        }
+        self.context.cache.evict_view(&inner.view);
+        inner.view = create_view(&texture, inner.view_dimension);
+        inner.texture = texture;
+        inner.generation += 1;
+    }
 
     pub fn from_bytes(bytes: &[u8], filter: FilterMode) -> Result<Self> {
         Self::from_bytes_with_address_mode(bytes, filter, AddressMode::ClampToEdge)
@@ -36,115 +785,443 @@ impl Texture {
         let img = image::load_from_memory(bytes)?;
         Self::from_image_with_addres_mode(&img, filter, address)
     }
-    pub fn from_image(img: &image::DynamicImage, filter: FilterMode) -> Result<Self> {
+    pub fn from_image(img: &DynamicImage, filter: FilterMode) -> Result<Self> {
         Self::from_image_with_addres_mode(img, filter, AddressMode::ClampToEdge)
     }
     /// From image from crate `image`
     pub fn from_image_with_addres_mode(
-        img: &image::DynamicImage,
+        img: &DynamicImage,
         filter: FilterMode,
         address: AddressMode,
     ) -> Result<Self> {
-        let rgba = img.to_rgba8();
-        let dimensions = img.dimensions();
-
-        let size = wgpu::Extent3d {
-            width: dimensions.0,
-            height: dimensions.1,
-            depth_or_array_layers: 1,
+        Self::from_image_in(&RenderContext::global(), img, filter, address)
+    }
+    /// From image from crate `image` on device of `context`
+    pub fn from_image_in(
+        context: &RenderContext,
+        img: &DynamicImage,
+        filter: FilterMode,
+        address: AddressMode,
+    ) -> Result<Self> {
+        Self::from_image_with_options_in(
+            context,
+            img,
+            &TextureOptions::default()
+                .filter(filter)
+                .address_mode(address),
+        )
+    }
+    /// From raw bytes of image file, see `from_image_with_options`
+    pub fn from_bytes_with_options(bytes: &[u8], options: &TextureOptions) -> Result<Self> {
+        let img = image::load_from_memory(bytes)?;
+        Self::from_image_with_options(&img, options)
+    }
+    /// Image is converted to `options.format`
+    pub fn from_image_with_options(img: &DynamicImage, options: &TextureOptions) -> Result<Self> {
+        Self::from_image_with_options_in(&RenderContext::global(), img, options)
+    }
+    /// See `from_image_with_options`
+    pub fn from_image_with_options_in(
+        context: &RenderContext,
+        img: &DynamicImage,
+        options: &TextureOptions,
+    ) -> Result<Self> {
+        let (width, height) = img.dimensions();
+        let texture = Self::empty_with_options_in(context, UVec2::new(width, height), options);
+        texture.write_region(
+            TextureRect::new(0, 0, width, height),
+            &options.format.convert(img),
+        )?;
+        texture.fill_mipmaps(Some(std::slice::from_ref(img)))?;
+        Ok(texture)
+    }
+    /// Array of equally sized images, `texture_2d_array` in shader. Layer is index
+    /// of image, so tiles and animation frames don't bleed into each other like in atlas
+    pub fn array_from_images(images: &[DynamicImage], options: &TextureOptions) -> Result<Self> {
+        Self::array_from_images_in(&RenderContext::global(), images, options)
+    }
+    /// See `array_from_images`
+    pub fn array_from_images_in(
+        context: &RenderContext,
+        images: &[DynamicImage],
+        options: &TextureOptions,
+    ) -> Result<Self> {
+        Self::from_layers_in(context, images, TextureViewDimension::D2Array, options)
+    }
+    /// 3D texture from equally sized slices along z, `texture_3d` in shader.
+    /// Mip chain is not made
+    pub fn volume_from_slices(slices: &[DynamicImage], options: &TextureOptions) -> Result<Self> {
+        Self::volume_from_slices_in(&RenderContext::global(), slices, options)
+    }
+    /// See `volume_from_slices`
+    pub fn volume_from_slices_in(
+        context: &RenderContext,
+        slices: &[DynamicImage],
+        options: &TextureOptions,
+    ) -> Result<Self> {
+        Self::from_layers_in(context, slices, TextureViewDimension::D3, options)
+    }
+    /// 3D texture from pixels in `options.format`, for LUTs.
+    /// Rows are tightly packed, slices along z follow each other
+    pub fn volume_from_bytes(size: UVec3, pixels: &[u8], options: &TextureOptions) -> Result<Self> {
+        Self::volume_from_bytes_in(&RenderContext::global(), size, pixels, options)
+    }
+    /// See `volume_from_bytes`
+    pub fn volume_from_bytes_in(
+        context: &RenderContext,
+        size: UVec3,
+        pixels: &[u8],
+        options: &TextureOptions,
+    ) -> Result<Self> {
+        let slice_len = (size.x as usize)
+            .checked_mul(size.y as usize)
+            .and_then(|len| len.checked_mul(options.format.bytes_per_pixel() as usize));
+        let volume_len = slice_len.and_then(|len| len.checked_mul(size.z as usize));
+        let (Some(slice_len), Some(volume_len)) = (slice_len, volume_len) else {
+            bail!("Volume {}x{}x{} is too large", size.x, size.y, size.z);
         };
-        let texture = UnMutRenderer::get()
-            .device
-            .create_texture(&wgpu::TextureDescriptor {
-                label: None,
-                size,
-                mip_level_count: 1,
-                sample_count: 1,
-                dimension: wgpu::TextureDimension::D2,
-                format: wgpu::TextureFormat::Rgba8UnormSrgb,
-                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-                view_formats: &[],
-            });
-
-        UnMutRenderer::get().queue.write_texture(
-            wgpu::TexelCopyTextureInfo {
-                aspect: wgpu::TextureAspect::All,
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-            },
-            &rgba,
-            wgpu::TexelCopyBufferLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * dimensions.0),
-                rows_per_image: Some(dimensions.1),
-            },
-            size,
+        if pixels.len() != volume_len {
+            bail!(
+                "Volume {}x{}x{} needs {} bytes, given {}",
+                size.x,
+                size.y,
+                size.z,
+                volume_len,
+                pixels.len()
+            );
+        }
+        let texture = Self::empty_layers_in(
+            context,
+            size.truncate(),
+            size.z,
+            TextureViewDimension::D3,
+            options,
         );
-
-        let view = texture.create_view(&wgpu::TextureViewDescriptor {
-            ..Default::default()
-        });
-        let sampler = UnMutRenderer::get()
-            .device
-            .create_sampler(&wgpu::SamplerDescriptor {
-                address_mode_u: address,
-                address_mode_v: address,
-                address_mode_w: address,
-                mag_filter: filter,
-                min_filter: wgpu::FilterMode::Nearest,
-                mipmap_filter: filter,
-                ..Default::default()
-            });
-
-        Ok(Self {
-            texture,
-            view,
-            sampler,
-        })
+        let rect = TextureRect::new(0, 0, size.x, size.y);
+        for (z, slice) in pixels.chunks(slice_len.max(1)).enumerate() {
+            texture.write_layer(z as u32, rect, slice)?;
+        }
+        Ok(texture)
+    }
+    /// Cubemap from square faces in order +X, -X, +Y, -Y, +Z, -Z,
+    /// `texture_cube` in shader
+    pub fn cubemap_from_faces(faces: &[DynamicImage; 6], options: &TextureOptions) -> Result<Self> {
+        Self::cubemap_from_faces_in(&RenderContext::global(), faces, options)
+    }
+    /// See `cubemap_from_faces`
+    pub fn cubemap_from_faces_in(
+        context: &RenderContext,
+        faces: &[DynamicImage; 6],
+        options: &TextureOptions,
+    ) -> Result<Self> {
+        let (width, height) = faces[0].dimensions();
+        if width != height {
+            bail!(
+                "Faces of cubemap must be square, given {}x{}",
+                width,
+                height
+            );
+        }
+        Self::from_layers_in(context, faces, TextureViewDimension::Cube, options)
+    }
+    /// Cubemap with faces of `face_size` projected from equirectangular panorama.
+    /// Projection is done on CPU, use float format to keep HDR
+    pub fn cubemap_from_equirectangular(
+        img: &DynamicImage,
+        face_size: u32,
+        options: &TextureOptions,
+    ) -> Result<Self> {
+        Self::cubemap_from_equirectangular_in(&RenderContext::global(), img, face_size, options)
+    }
+    /// See `cubemap_from_equirectangular`
+    pub fn cubemap_from_equirectangular_in(
+        context: &RenderContext,
+        img: &DynamicImage,
+        face_size: u32,
+        options: &TextureOptions,
+    ) -> Result<Self> {
+        if img.width() == 0 || img.height() == 0 {
+            bail!("Panorama is empty");
+        }
+        let faces = equirectangular_to_faces(&img.to_rgba32f(), face_size.max(1));
+        Self::cubemap_from_faces_in(context, &faces, options)
+    }
+    /// Images are layers of texture with `view_dimension`
+    fn from_layers_in(
+        context: &RenderContext,
+        images: &[DynamicImage],
+        view_dimension: TextureViewDimension,
+        options: &TextureOptions,
+    ) -> Result<Self> {
+        let Some(first) = images.first() else {
+            bail!("No images for texture");
+        };
+        let (width, height) = first.dimensions();
+        if let Some(other) = images
+            .iter()
+            .find(|img| img.dimensions() != (width, height))
+        {
+            let (other_width, other_height) = other.dimensions();
+            bail!(
+                "Images must have same size, {}x{} and {}x{} given",
+                width,
+                height,
+                other_width,
+                other_height
+            );
+        }
+        let texture = Self::empty_layers_in(
+            context,
+            UVec2::new(width, height),
+            images.len() as u32,
+            view_dimension,
+            options,
+        );
+        let rect = TextureRect::new(0, 0, width, height);
+        for (layer, img) in images.iter().enumerate() {
+            texture.write_layer(layer as u32, rect, &options.format.convert(img))?;
+        }
+        texture.fill_mipmaps(Some(images))?;
+        Ok(texture)
     }
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
 
+    /// Depth texture for surface of global context
     pub fn create_depth_texture(surface: SurfaceId) -> Self {
-        let config = Surfaces::get().get_surface(surface).config.clone();
+        Self::create_depth_texture_in(&RenderContext::global(), surface)
+    }
+    /// Depth texture for surface of `context`
+    pub fn create_depth_texture_in(context: &RenderContext, surface: SurfaceId) -> Self {
+        let config = context.surfaces().get_surface(surface).config.clone();
 
-        let size = wgpu::Extent3d {
-            width: config.width.max(1),
-            height: config.height.max(1),
-            depth_or_array_layers: 1,
-        };
-        let desc = wgpu::TextureDescriptor {
-            label: None,
-            size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: Self::DEPTH_FORMAT,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
-            view_formats: &[],
+        let texture = create_texture(
+            context,
+            None,
+            wgpu::Extent3d {
+                width: config.width.max(1),
+                height: config.height.max(1),
+                depth_or_array_layers: 1,
+            },
+            TextureViewDimension::D2,
+            1,
+            Self::DEPTH_FORMAT,
+            TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+        );
+        let sampler = SamplerOptions {
+            mipmap_filter: FilterMode::Nearest,
+            lod_max_clamp: 100.0,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
         };
-        let texture = UnMutRenderer::get().device.create_texture(&desc);
-
-        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = UnMutRenderer::get()
-            .device
-            .create_sampler(&wgpu::SamplerDescriptor {
-                address_mode_u: wgpu::AddressMode::ClampToEdge,
-                address_mode_v: wgpu::AddressMode::ClampToEdge,
-                address_mode_w: wgpu::AddressMode::ClampToEdge,
-                mag_filter: wgpu::FilterMode::Linear,
-                min_filter: wgpu::FilterMode::Linear,
-                mipmap_filter: wgpu::FilterMode::Nearest,
-                compare: Some(wgpu::CompareFunction::LessEqual),
-                lod_min_clamp: 0.0,
-                lod_max_clamp: 100.0,
-                ..Default::default()
-            });
 
-        Self {
-            texture,
-            view,
-            sampler,
+        Self::from_parts(context, texture, None, TextureViewDimension::D2, sampler)
+    }
+}
+
+fn create_texture(
+    context: &RenderContext,
+    label: Option<&str>,
+    size: wgpu::Extent3d,
+    view_dimension: TextureViewDimension,
+    mip_level_count: u32,
+    format: wgpu::TextureFormat,
+    usage: TextureUsages,
+) -> wgpu::Texture {
+    context.device().create_texture(&wgpu::TextureDescriptor {
+        label,
+        size,
+        mip_level_count,
+        sample_count: 1,
+        dimension: view_dimension.compatible_texture_dimension(),
+        format,
+        usage,
+        view_formats: &[],
+    })
+}
+
+fn create_view(texture: &wgpu::Texture, dimension: TextureViewDimension) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(dimension),
+        ..Default::default()
+    })
+}
+
+/// Mip chain of texture can be drawn on GPU, see `mipmap` module
+fn can_generate_mipmaps(texture: &wgpu::Texture) -> bool {
+    texture.dimension() == wgpu::TextureDimension::D2
+        && mipmap::is_gpu_supported(texture.format())
+        && texture
+            .usage()
+            .contains(TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING)
+}
+
+/// Faces in order +X, -X, +Y, -Y, +Z, -Z. Panorama wraps horizontally,
+/// its top is +Y
+fn equirectangular_to_faces(img: &Rgba32FImage, face_size: u32) -> [DynamicImage; 6] {
+    let (width, height) = img.dimensions();
+    let texel = |x: i64, y: i64| {
+        let x = x.rem_euclid(width as i64) as u32;
+        let y = y.clamp(0, height as i64 - 1) as u32;
+        img.get_pixel(x, y).0
+    };
+    // Bilinear
+    let sample = |u: f32, v: f32| {
+        let x = u * width as f32 - 0.5;
+        let y = v * height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let mut out = [0.0; 4];
+        for (dx, dy, weight) in [
+            (0, 0, (1.0 - fx) * (1.0 - fy)),
+            (1, 0, fx * (1.0 - fy)),
+            (0, 1, (1.0 - fx) * fy),
+            (1, 1, fx * fy),
+        ] {
+            let pixel = texel(x0 + dx, y0 + dy);
+            for (o, p) in out.iter_mut().zip(pixel) {
+                *o += p * weight;
+            }
+        }
+        image::Rgba(out)
+    };
+    std::array::from_fn(|face| {
+        let face_img = Rgba32FImage::from_fn(face_size, face_size, |x, y| {
+            let u = 2.0 * (x as f32 + 0.5) / face_size as f32 - 1.0;
+            let v = 2.0 * (y as f32 + 0.5) / face_size as f32 - 1.0;
+            let dir = match face {
+                0 => Vec3::new(1.0, -v, -u),
+                1 => Vec3::new(-1.0, -v, u),
+                2 => Vec3::new(u, 1.0, v),
+                3 => Vec3::new(u, -1.0, -v),
+                4 => Vec3::new(u, -v, 1.0),
+                _ => Vec3::new(-u, -v, -1.0),
+            }
+            .normalize();
+            let longitude = dir.z.atan2(dir.x);
+            let latitude = dir.y.asin();
+            sample(
+                longitude / std::f32::consts::TAU + 0.5,
+                0.5 - latitude / std::f32::consts::PI,
+            )
+        });
+        DynamicImage::ImageRgba32F(face_img)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{ImageBuffer, Luma, Rgba};
+
+    use super::*;
+
+    #[test]
+    fn f16_bits() {
+        assert_eq!(f32_to_f16(0.), 0);
+        assert_eq!(f32_to_f16(-0.), 0x8000);
+        assert_eq!(f32_to_f16(1.), 0x3c00);
+        assert_eq!(f32_to_f16(0.5), 0x3800);
+        assert_eq!(f32_to_f16(-2.), 0xc000);
+        assert_eq!(f32_to_f16(1. / 3.), 0x3555);
+        // Largest and smallest normal
+        assert_eq!(f32_to_f16(65504.), 0x7bff);
+        assert_eq!(f32_to_f16(2f32.powi(-14)), 0x0400);
+        // Subnormals, too small is zero
+        assert_eq!(f32_to_f16(2f32.powi(-15)), 0x0200);
+        assert_eq!(f32_to_f16(2f32.powi(-24)), 0x0001);
+        assert_eq!(f32_to_f16(2f32.powi(-25)), 0);
+        assert_eq!(f32_to_f16(-(2f32.powi(-25))), 0x8000);
+        // Too big is infinity
+        assert_eq!(f32_to_f16(65536.), 0x7c00);
+        assert_eq!(f32_to_f16(f32::INFINITY), 0x7c00);
+        assert_eq!(f32_to_f16(f32::NEG_INFINITY), 0xfc00);
+        assert_eq!(f32_to_f16(f32::NAN) & 0x7fff, 0x7e00);
+    }
+
+    #[test]
+    fn convert_rgba8() {
+        let img = DynamicImage::ImageRgba8(ImageBuffer::from_fn(2, 1, |x, _| {
+            if x == 0 {
+                Rgba([255, 255, 255, 128])
+            } else {
+                Rgba([0, 0, 0, 255])
+            }
+        }));
+        assert_eq!(
+            PixelFormat::Rgba8.convert(&img),
+            [255, 255, 255, 128, 0, 0, 0, 255]
+        );
+        assert_eq!(
+            PixelFormat::Rgba8Srgb.convert(&img),
+            PixelFormat::Rgba8.convert(&img)
+        );
+        assert_eq!(PixelFormat::R8.convert(&img), [255, 0]);
+        assert_eq!(PixelFormat::Rg8.convert(&img), [255, 128, 0, 255]);
+
+        let half = PixelFormat::Rgba16Float.convert(&img);
+        assert_eq!(
+            half.len(),
+            2 * PixelFormat::Rgba16Float.bytes_per_pixel() as usize
+        );
+        assert_eq!(&half[0..2], 0x3c00u16.to_le_bytes());
+        assert_eq!(&half[8..10], [0, 0]);
+        assert_eq!(&half[14..16], 0x3c00u16.to_le_bytes());
+
+        let float = PixelFormat::Rgba32Float.convert(&img);
+        assert_eq!(
+            float.len(),
+            2 * PixelFormat::Rgba32Float.bytes_per_pixel() as usize
+        );
+        assert_eq!(&float[0..4], 1f32.to_le_bytes());
+        assert_eq!(&float[16..20], 0f32.to_le_bytes());
+    }
+
+    #[test]
+    fn convert_r32uint_keeps_values() {
+        let img = DynamicImage::ImageLuma8(ImageBuffer::from_raw(2, 1, vec![7, 255]).unwrap());
+        let values = PixelFormat::R32Uint.convert(&img);
+        assert_eq!(bytemuck::cast_slice::<u8, u32>(&values), [7, 255]);
+
+        let img: ImageBuffer<Luma<u16>, _> =
+            ImageBuffer::from_raw(2, 1, vec![1000, 65535]).unwrap();
+        let values = PixelFormat::R32Uint.convert(&DynamicImage::ImageLuma16(img));
+        assert_eq!(bytemuck::cast_slice::<u8, u32>(&values), [1000, 65535]);
+    }
+
+    #[test]
+    fn wgpu_round_trip() {
+        for format in [
+            PixelFormat::R8,
+            PixelFormat::Rg8,
+            PixelFormat::Rgba8,
+            PixelFormat::Rgba8Srgb,
+            PixelFormat::Rgba16Float,
+            PixelFormat::Rgba32Float,
+            PixelFormat::R32Uint,
+        ] {
+            assert_eq!(PixelFormat::from_wgpu(format.wgpu()), Some(format));
         }
+        assert_eq!(
+            PixelFormat::from_wgpu(wgpu::TextureFormat::Bgra8Unorm),
+            None
+        );
+    }
+
+    #[test]
+    fn sample_types() {
+        use wgpu::TextureSampleType;
+        assert_eq!(PixelFormat::R32Uint.sample_type(), TextureSampleType::Uint);
+        assert_eq!(
+            PixelFormat::Rgba32Float.sample_type(),
+            TextureSampleType::Float { filterable: false }
+        );
+        assert_eq!(
+            PixelFormat::Rgba16Float.sample_type(),
+            TextureSampleType::Float { filterable: true }
+        );
+        assert_eq!(
+            PixelFormat::Rgba8Srgb.sample_type(),
+            TextureSampleType::Float { filterable: true }
+        );
     }
 }
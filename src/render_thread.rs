@@ -0,0 +1,98 @@
+//! Render thread of `AppConfig::render_thread`. Frames from `CatApp::extract`
+//! are rendered one frame behind `update`: while frame is rendered, next one is
+//! simulated. Only one frame waits for render, next `submit` waits for it
+
+use std::{
+    sync::{
+        mpsc::{sync_channel, SyncSender},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+};
+
+use winit::{dpi::PhysicalSize, window::WindowId};
+
+use crate::{
+    app::FrameSnapshot,
+    render::{stats::RenderStats, Renderer},
+};
+
+/// Renderer moved to render thread and what event loop needs from it without
+/// waiting for frame
+pub(crate) struct FrameShared {
+    pub renderer: Mutex<Renderer>,
+    /// Statistics of last rendered frame
+    pub stats: Mutex<RenderStats>,
+    /// Surfaces are resized before next frame, not while it is rendered
+    pub resizes: Mutex<Vec<(WindowId, PhysicalSize<u32>)>>,
+}
+
+impl FrameShared {
+    pub(crate) fn new(renderer: Renderer) -> Self {
+        Self {
+            stats: Mutex::new(renderer.render_stats().clone()),
+            renderer: Mutex::new(renderer),
+            resizes: Mutex::default(),
+        }
+    }
+}
+
+/// One frame: shader reload, `render` and end of frame
+pub(crate) fn render_frame(renderer: &mut Renderer, render: impl FnOnce(&mut Renderer)) {
+    renderer.reload_changed_shaders();
+    render(renderer);
+    renderer.end_frame();
+}
+
+pub(crate) struct RenderThread {
+    sender: Option<SyncSender<Box<dyn FrameSnapshot>>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl RenderThread {
+    pub(crate) fn spawn(shared: Arc<FrameShared>) -> Self {
+        let (sender, receiver) = sync_channel::<Box<dyn FrameSnapshot>>(1);
+        let handle = std::thread::Builder::new()
+            .name("cat_render".to_string())
+            .spawn(move || {
+                for frame in receiver {
+                    let mut renderer = shared.renderer.lock().unwrap();
+                    // Event loop thread recovers device
+                    if renderer.is_device_lost() {
+                        continue;
+                    }
+                    // `submit` fails after it, so app exits
+                    if renderer.needs_exit {
+                        break;
+                    }
+                    for (window, size) in std::mem::take(&mut *shared.resizes.lock().unwrap()) {
+                        renderer.on_resize(&window, size);
+                    }
+                    render_frame(&mut renderer, |renderer| frame.render(renderer));
+                    *shared.stats.lock().unwrap() = renderer.render_stats().clone();
+                }
+            })
+            .expect("Failed to spawn render thread");
+        Self {
+            sender: Some(sender),
+            handle: Some(handle),
+        }
+    }
+    /// Sends frame for render, waits if previous one is not started.
+    /// Returns false if render thread is stopped (renderer needs exit or it panicked)
+    pub(crate) fn submit(&self, frame: Box<dyn FrameSnapshot>) -> bool {
+        self.sender.as_ref().unwrap().send(frame).is_ok()
+    }
+}
+
+impl Drop for RenderThread {
+    /// Renders frame which is sent and stops
+    fn drop(&mut self) {
+        self.sender = None;
+        if let Some(handle) = self.handle.take() {
+            if handle.join().is_err() {
+                log::error!("Render thread panicked");
+            }
+        }
+    }
+}
@@ -3,6 +3,7 @@
 use std::{
     fs::OpenOptions,
     io::Write,
+    path::PathBuf,
     sync::{Arc, LazyLock, Mutex, MutexGuard},
 };
 
@@ -33,4 +34,31 @@ impl Filesystem {
         file.write_all(&bytes)?;
         Ok(())
     }
+    /// Writes into temp file and renames it, so file is never half written
+    pub fn write_all_atomic(&self, path: &str, bytes: Vec<u8>) -> Result<(), std::io::Error> {
+        let temp = format!("{}.temp", path);
+        std::fs::write(&temp, bytes)?;
+        std::fs::rename(&temp, path)
+    }
+    /// Directory for app data (caches, saves), it is created if not exists
+    /// Linux: `$XDG_DATA_HOME/app_name` or `~/.local/share/app_name`
+    /// Windows: `%APPDATA%/app_name`
+    /// MacOS: `~/Library/Application Support/app_name`
+    pub fn data_dir(&self, app_name: &str) -> Result<PathBuf, std::io::Error> {
+        let env = |name: &str| std::env::var_os(name).filter(|v| !v.is_empty());
+        let base = if cfg!(target_os = "windows") {
+            env("APPDATA").map(PathBuf::from)
+        } else if cfg!(target_os = "macos") {
+            env("HOME").map(|h| PathBuf::from(h).join("Library/Application Support"))
+        } else {
+            env("XDG_DATA_HOME")
+                .map(PathBuf::from)
+                .or_else(|| env("HOME").map(|h| PathBuf::from(h).join(".local/share")))
+        };
+        let dir = base
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "No home directory"))?
+            .join(app_name);
+        std::fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
 }
@@ -1,6 +1,7 @@
 use crate::context::AppContext;
 
 pub mod sprite;
+pub mod sprite_batch;
 pub mod texture_atlas;
 
 pub fn init_render_utils(context: &mut AppContext) {
@@ -6,14 +6,17 @@ use crate::{
     context::AppContext,
     render::{
         bind_group::BindGroup,
+        buffer::{CatUniform, CatVertex},
         camera::CameraProjection,
         mesh::{Material, MaterialLayout, MaterialLayoutBuilder, Mesh, MeshRef},
         render_pipeline::PipelineOptions,
         small::{Rect, Transform},
         texture::Texture,
-        Render,
+        Color, Render,
     },
 };
+
+use super::sprite_batch::SpriteInstance;
 pub fn init_sprites(_context: &mut AppContext) {}
 pub struct Sprite {
     size: Vec2,
@@ -54,6 +57,22 @@ impl Sprite {
     pub fn get_texture(&self) -> Texture {
         self.render.texture.clone()
     }
+    pub(crate) fn get_texture_ref(&self) -> &Texture {
+        &self.render.texture
+    }
+    pub fn set_tint(&mut self, tint: Color) {
+        self.render.update_tint(tint);
+    }
+    pub fn get_tint(&self) -> Color {
+        self.render.tint
+    }
+    /// Layer of `queue`: sprites of lower layer are drawn first, in layer they are sorted by depth
+    pub fn set_layer(&mut self, layer: i32) {
+        self.render.layer = layer;
+    }
+    pub fn get_layer(&self) -> i32 {
+        self.render.layer
+    }
     pub fn new(
         layout: &SpriteLayout,
         orig_width: f32,
@@ -128,9 +147,9 @@ impl Sprite {
         self.render.update_transform(transform);
         self.update_box();
     }
-    pub fn render(&mut self, render: &mut Render) {
-        let proj = render.get_projection();
-        let need_render = match proj {
+    /// Is sprite seen by camera of render
+    pub fn is_visible(&self, render: &Render) -> bool {
+        match render.get_projection() {
             CameraProjection::P2D {
                 near: _,
                 far: _,
@@ -140,11 +159,38 @@ impl Sprite {
                 // && self.transform.translation.z >= near
                 // && self.transform.translation.z <= far
             }
-        };
-        if need_render {
+        }
+    }
+    /// Draw now, in order of calls
+    pub fn render(&mut self, render: &mut Render) {
+        if self.is_visible(render) {
             self.render.render(render);
         }
     }
+    /// Draw is queued as transparent, sprites are drawn from farthest at end of pass
+    pub fn queue(&mut self, render: &mut Render) {
+        if self.is_visible(render) {
+            self.render.queue(render);
+        }
+    }
+    /// Data of sprite for `SpriteBatch`
+    pub(crate) fn get_instance(&self) -> SpriteInstance {
+        let texture_size = self.render.texture.get_size().as_vec2();
+        let rect = self.render.rect;
+        SpriteInstance {
+            model: (self.transform.get_matrix()
+                * Mat4::from_scale(Vec3::new(self.render.size.x, self.render.size.y, 1.))
+                * Mat4::from_translation(Vec3::new(-self.origin.x, self.origin.y, 0.)))
+            .to_cols_array_2d(),
+            uv_rect: [
+                rect.min.x / texture_size.x,
+                rect.min.y / texture_size.y,
+                rect.max.x / texture_size.x,
+                rect.max.y / texture_size.y,
+            ],
+            tint: self.render.tint.to_array(),
+        }
+    }
 }
 
 pub struct SpriteRender {
@@ -156,12 +202,31 @@ pub struct SpriteRender {
     updated_rect: bool,
     updated_texture: bool,
     rect: Rect,
+    tint: Color,
+    layer: i32,
 
     material: Option<Material>,
     layout: Option<MaterialLayout>,
 }
 impl SpriteRender {
     pub fn render(&mut self, render: &mut Render) {
+        self.prepare(render);
+        self.mesh
+            .draw_with_material(render, self.material.as_ref().unwrap());
+    }
+    /// Queued as transparent by layer and depth
+    pub fn queue(&mut self, render: &mut Render) {
+        self.prepare(render);
+        let depth = render.view_depth(self.transform.translation);
+        self.mesh.queue_transparent_with_material(
+            render,
+            self.material.as_ref().unwrap(),
+            self.layer,
+            depth,
+        );
+    }
+    /// Material with changes and camera for draw
+    fn prepare(&mut self, render: &mut Render) {
         if self.material.is_none() {
             let rect = self.get_texture_rect();
             let uni = self.get_uniform();
@@ -194,8 +259,6 @@ impl SpriteRender {
                 .change_textures(vec![(1, 2, self.texture.clone())]);
         }
         render.use_camera_uniform_at(1);
-        self.mesh
-            .draw_with_material(render, self.material.as_mut().unwrap());
     }
     pub fn change_texture(&mut self, texture: Texture) {
         self.texture = texture;
@@ -219,12 +282,18 @@ impl SpriteRender {
             rect,
             updated_rect: false,
             updated_texture: false,
+            tint: Color::WHITE,
+            layer: 0,
         }
     }
     pub fn update_rect(&mut self, rect: Rect) {
         self.rect = rect;
         self.updated_rect = true;
     }
+    pub fn update_tint(&mut self, tint: Color) {
+        self.tint = tint;
+        self.updated_rect = true;
+    }
     fn get_texture_rect(&self) -> TextureRectUniform {
         TextureRectUniform {
             size: [
@@ -237,6 +306,8 @@ impl SpriteRender {
                 self.texture.get_size().x as f32,
                 self.texture.get_size().y as f32,
             ],
+            _padding: [0.; 2],
+            tint: self.tint.to_array(),
         }
     }
     fn update_rect_inner(&mut self) {
@@ -244,7 +315,7 @@ impl SpriteRender {
         self.material
             .as_mut()
             .unwrap()
-            .update_uniform(3, bytemuck::bytes_of(&rect).to_vec());
+            .update_uniform_typed(3, &rect);
     }
     pub fn update_size(&mut self, size: Vec2) {
         self.size = size;
@@ -262,7 +333,7 @@ impl SpriteRender {
         self.material
             .as_mut()
             .unwrap()
-            .update_uniform(0, bytemuck::bytes_of(&uni).to_vec());
+            .update_uniform_typed(0, &uni);
     }
     pub fn update_transform(&mut self, transform: Transform) {
         self.transform = transform;
@@ -296,10 +367,13 @@ impl SpriteLayout {
         material_layout.register_texture_at(1, 2, ShaderStages::FRAGMENT);
         material_layout.register_uniform_at(0, ShaderStages::VERTEX);
         material_layout.register_uniform_at(3, ShaderStages::VERTEX);
-        let material_layout = material_layout.build(context.get_mut_renderer());
+        let material_layout = context
+            .with_renderer(|renderer| material_layout.build(renderer))
+            .expect("Sprite shader is invalid");
         Self {
             material_layout,
-            mesh: Mesh::new(
+            mesh: Mesh::new_in(
+                &context.render_context(),
                 vec![
                     Vertex {
                         position: [0. - origin.x, 0. + origin.y, 0.],
@@ -330,36 +404,24 @@ impl SpriteLayout {
 }
 
 #[repr(C)]
-#[derive(Copy, Clone, Debug, Pod, Zeroable)]
-struct Vertex {
-    position: [f32; 3],
-    tex_coords: [f32; 2],
-}
-impl Vertex {
-    const ATTRIBS: [wgpu::VertexAttribute; 2] =
-        wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x2];
-
-    fn desc() -> wgpu::VertexBufferLayout<'static> {
-        use std::mem;
-
-        wgpu::VertexBufferLayout {
-            array_stride: mem::size_of::<Self>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &Self::ATTRIBS,
-        }
-    }
+#[derive(Copy, Clone, Debug, Pod, Zeroable, CatVertex)]
+pub(crate) struct Vertex {
+    pub(crate) position: [f32; 3],
+    pub(crate) tex_coords: [f32; 2],
 }
 #[repr(C)]
 // This is so we can store this in a buffer
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, CatUniform)]
 struct SpriteUniform {
     view_proj: [[f32; 4]; 4],
 }
 
 #[repr(C)]
 // This is so we can store this in a buffer
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, CatUniform)]
 struct TextureRectUniform {
     size: [f32; 4],
     texture_size: [f32; 2],
+    _padding: [f32; 2],
+    tint: [f32; 4],
 }
@@ -0,0 +1,207 @@
+//! `SpriteBatch` draws many sprites with one draw call per texture
+
+use std::{collections::HashMap, ops::Range};
+
+use bytemuck::{Pod, Zeroable};
+use glam::Vec3;
+use wgpu::{BlendState, BufferUsages, ShaderStages};
+
+use crate::{
+    context::AppContext,
+    render::{
+        bind_group::BindGroup,
+        buffer::{Buffer, CatVertex},
+        mesh::{Material, MaterialLayout, MaterialLayoutBuilder, Mesh, MeshRef},
+        render_pipeline::PipelineOptions,
+        texture::Texture,
+        Render,
+    },
+};
+
+use super::sprite::{Sprite, Vertex};
+
+/// Pipeline and quad shared by sprite batches
+#[derive(Clone)]
+pub struct SpriteBatchLayout {
+    material_layout: MaterialLayout,
+    mesh: MeshRef,
+}
+
+impl SpriteBatchLayout {
+    pub fn new(context: &mut AppContext, camera: BindGroup) -> Self {
+        let mut material_layout = MaterialLayoutBuilder::new(PipelineOptions {
+            vertex_shader: include_str!("sprite_batch_shader.wgsl").to_string(),
+            vertex_entry_point: String::from("vs_main"),
+            fragment_entry_point: String::from("fs_main"),
+            bind_group_layouts: vec![camera.layout()],
+            buffers: vec![Vertex::desc(), SpriteInstance::desc()],
+            frag_blend: Some(BlendState::ALPHA_BLENDING),
+            ..Default::default()
+        });
+        material_layout.register_texture_at(0, 1, ShaderStages::FRAGMENT);
+        let material_layout = context
+            .with_renderer(|renderer| material_layout.build(renderer))
+            .expect("Sprite batch shader is invalid");
+        Self {
+            material_layout,
+            // Origin is part of every instance matrix
+            mesh: Mesh::new_in(
+                &context.render_context(),
+                vec![
+                    Vertex {
+                        position: [0., 0., 0.],
+                        tex_coords: [0., 0.],
+                    },
+                    Vertex {
+                        position: [0., -1., 0.],
+                        tex_coords: [0., 1.],
+                    },
+                    Vertex {
+                        position: [1., 0., 0.],
+                        tex_coords: [1., 0.],
+                    },
+                    Vertex {
+                        position: [1., -1., 0.],
+                        tex_coords: [1., 1.],
+                    },
+                ],
+                vec![3, 2, 0, 0, 1, 3],
+            )
+            .ref_me(),
+        }
+    }
+}
+
+/// Collects sprites and draws them from farthest, neighbour sprites with same texture
+/// are drawn in one call. Sprites on same depth are grouped by texture in order of
+/// first pushed sprite of texture, so flat scenes have one call per texture.
+pub struct SpriteBatch {
+    layout: SpriteBatchLayout,
+    groups: Vec<(Texture, Vec<SpriteInstance>)>,
+    group_ids: HashMap<Texture, usize>,
+    materials: HashMap<Texture, Material>,
+    instances: Vec<SpriteInstance>,
+    buffer: Option<Buffer<SpriteInstance>>,
+    capacity: usize,
+}
+
+impl SpriteBatch {
+    pub fn new(layout: &SpriteBatchLayout) -> Self {
+        Self {
+            layout: layout.clone(),
+            groups: Vec::new(),
+            group_ids: HashMap::new(),
+            materials: HashMap::new(),
+            instances: Vec::new(),
+            buffer: None,
+            capacity: 0,
+        }
+    }
+    /// Add sprite for next `render`, sprite is not culled
+    pub fn push(&mut self, sprite: &Sprite) {
+        let texture = sprite.get_texture_ref();
+        let id = match self.group_ids.get(texture) {
+            Some(id) => *id,
+            None => {
+                self.groups.push((texture.clone(), Vec::new()));
+                self.group_ids
+                    .insert(texture.clone(), self.groups.len() - 1);
+                self.groups.len() - 1
+            }
+        };
+        self.groups[id].1.push(sprite.get_instance());
+    }
+    /// Number of sprites waiting for render
+    pub fn len(&self) -> usize {
+        self.groups.iter().map(|(_, g)| g.len()).sum()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Pushes only visible sprites and renders them
+    pub fn draw_sprites<'a>(
+        &mut self,
+        render: &mut Render,
+        sprites: impl IntoIterator<Item = &'a Sprite>,
+    ) {
+        for sprite in sprites {
+            if sprite.is_visible(render) {
+                self.push(sprite);
+            }
+        }
+        self.render(render);
+    }
+    /// Uploads all pushed sprites and draws them, after it batch is empty.
+    /// Sprites are drawn from farthest, camera must be set
+    pub fn render(&mut self, render: &mut Render) {
+        let mut sorted = self
+            .groups
+            .iter()
+            .enumerate()
+            .flat_map(|(id, (_, group))| group.iter().map(move |instance| (id, instance)))
+            .map(|(id, instance)| {
+                let depth = render.view_depth(Vec3::from_slice(&instance.model[3]));
+                (depth, id, instance)
+            })
+            .collect::<Vec<_>>();
+        // Stable, so sprites on same depth stay grouped by texture
+        sorted.sort_by(|a, b| b.0.total_cmp(&a.0));
+        self.instances.clear();
+        let mut ranges: Vec<(usize, Range<u32>)> = Vec::new();
+        for (_, id, instance) in sorted {
+            let index = self.instances.len() as u32;
+            self.instances.push(*instance);
+            match ranges.last_mut() {
+                Some((last, range)) if *last == id => range.end = index + 1,
+                _ => ranges.push((id, index..index + 1)),
+            }
+        }
+        let ranges = ranges
+            .into_iter()
+            .map(|(id, range)| (self.groups[id].0.clone(), range))
+            .collect::<Vec<_>>();
+        self.groups.clear();
+        self.group_ids.clear();
+        if self.instances.is_empty() {
+            return;
+        }
+
+        if self.instances.len() > self.capacity || self.buffer.is_none() {
+            self.capacity = self.instances.len().next_power_of_two();
+            self.buffer = Some(Buffer::new_in(
+                render.context(),
+                vec![SpriteInstance::zeroed(); self.capacity],
+                BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            ));
+        }
+        let buffer = self.buffer.as_mut().unwrap();
+        buffer.update(self.instances.clone());
+
+        // Forget textures which are not drawn anymore
+        self.materials
+            .retain(|texture, _| ranges.iter().any(|(t, _)| t == texture));
+
+        render.use_camera_uniform_at(1);
+        render.set_vertex_buffer(buffer, 1, ..);
+        for (texture, range) in ranges {
+            let material = self.materials.entry(texture.clone()).or_insert_with(|| {
+                Material::from_layout(&self.layout.material_layout, vec![], vec![(0, 1, texture)])
+            });
+            self.layout
+                .mesh
+                .draw_instanced_with_material(render, material, range);
+        }
+    }
+}
+
+/// Per sprite data in instance buffer
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable, CatVertex)]
+#[cat_vertex(instance)]
+pub(crate) struct SpriteInstance {
+    #[cat_vertex(location = 2)]
+    pub(crate) model: [[f32; 4]; 4],
+    /// min and max of texture rect in uv
+    pub(crate) uv_rect: [f32; 4],
+    pub(crate) tint: [f32; 4],
+}
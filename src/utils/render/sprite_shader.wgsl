@@ -14,21 +14,21 @@ struct TextureOptions {
     size_w: f32,
     texture_size_x: f32,
     texture_size_y: f32,
+    _padding_x: f32,
+    _padding_y: f32,
+    tint: vec4<f32>,
 };
 @group(0) @binding(0) 
 var<uniform> uni: Uniform;
 @group(0) @binding(3) 
 var<uniform> texture_opt: TextureOptions;
 
-struct CameraUniform {
-    proj: mat4x4<f32>,
-};
-@group(1) @binding(0) 
-var<uniform> cam_uni: CameraUniform;
+#include cat_render::camera
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
+    @location(1) tint: vec4<f32>,
 }
 
 @vertex
@@ -46,6 +46,7 @@ fn vs_main(
     } else {
         out.tex_coords.y = texture_opt.size_w / texture_opt.texture_size_y;
     }
+    out.tint = texture_opt.tint;
     out.clip_position = cam_uni.proj * (uni.view_proj * vec4<f32>(model.position , 1.0));
     return out;
 }
@@ -59,5 +60,5 @@ var s_diffuse: sampler;
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    return textureSample(t_diffuse, s_diffuse, in.tex_coords) * in.tint;
 }
\ No newline at end of file
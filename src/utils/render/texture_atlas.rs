@@ -5,6 +5,11 @@ use crate::render::small::Rect;
 pub struct TextureAtlas {
     textures: Vec<Rect>,
 }
+impl Default for TextureAtlas {
+    fn default() -> Self {
+        TextureAtlas::new()
+    }
+}
 impl TextureAtlas {
     pub fn from_gird(size: Vec2, rows: usize, columns: usize) -> Self {
         let mut textures = Vec::new();
@@ -1,56 +1,31 @@
-use std::{
-    collections::HashMap,
-    sync::{LazyLock, Mutex, MutexGuard},
-};
-
 use cosmic_text::{fontdb::Source, Attrs, Buffer, FontSystem, Metrics, Shaping, SwashCache};
 use glam::Vec2;
 use image::DynamicImage;
 
-use crate::render::{texture::Texture, Color};
-
-struct GlobalFontResources {
-    pub swash_cache: SwashCache,
-    pub system_font: Font,
-}
-impl GlobalFontResources {
-    fn new() -> Self {
-        Self {
-            swash_cache: SwashCache::new(),
-            system_font: Font::system_font(),
-        }
-    }
-    pub fn get_mut() -> MutexGuard<'static, Self> {
-        log::trace!(
-            "GLOBAL FONT RESOURCES WRITE {:?}",
-            std::thread::current().id()
-        );
-        GLOBAL_FONT_RES.lock().unwrap()
-    }
-}
-static GLOBAL_FONT_RES: LazyLock<Mutex<GlobalFontResources>> =
-    LazyLock::new(|| Mutex::new(GlobalFontResources::new()));
+use crate::render::{texture::Texture, Color, RenderContext};
 
+/// Font with its own glyph cache, fonts don't share anything
 pub struct Font {
     system: FontSystem,
     buffer: Buffer,
+    swash_cache: SwashCache,
 }
 
 impl Font {
-    fn system_font() -> Self {
-        let mut sys = FontSystem::new();
-        let buf = Buffer::new(&mut sys, Metrics::new(1., 1.));
-        Self {
-            system: FontSystem::new(),
-            buffer: buf,
-        }
+    /// Fonts of system
+    pub fn system_font() -> Self {
+        Self::from_system(FontSystem::new())
     }
+    /// Only fonts of `source`
     pub fn new(source: Source) -> Self {
-        let mut sys = FontSystem::new_with_fonts(vec![source]);
-        let buf = Buffer::new(&mut sys, Metrics::new(1., 1.));
+        Self::from_system(FontSystem::new_with_fonts(vec![source]))
+    }
+    fn from_system(mut system: FontSystem) -> Self {
+        let buffer = Buffer::new(&mut system, Metrics::new(1., 1.));
         Self {
-            system: FontSystem::new(),
-            buffer: buf,
+            system,
+            buffer,
+            swash_cache: SwashCache::new(),
         }
     }
     pub fn render_to_new_texture(
@@ -62,9 +37,26 @@ impl Font {
         color: Color,
         font_attrs: Attrs,
     ) -> Texture {
-        Texture::from_image(
+        let context = RenderContext::global();
+        self.render_to_new_texture_in(&context, text, font_size, line_h, max, color, font_attrs)
+    }
+    /// See `render_to_new_texture`, texture is on device of `context`
+    #[allow(clippy::too_many_arguments)]
+    pub fn render_to_new_texture_in(
+        &mut self,
+        context: &RenderContext,
+        text: &str,
+        font_size: f32,
+        line_h: f32,
+        max: Vec2,
+        color: Color,
+        font_attrs: Attrs,
+    ) -> Texture {
+        Texture::from_image_in(
+            context,
             &self.render_to_image(text, font_size, line_h, max, color, font_attrs),
             wgpu::FilterMode::Linear,
+            wgpu::AddressMode::ClampToEdge,
         )
         .unwrap()
     }
@@ -78,8 +70,11 @@ impl Font {
         color: Color,
         font_attrs: Attrs,
     ) -> DynamicImage {
-        let mut res = GlobalFontResources::get_mut();
-        let Font { system, buffer } = self;
+        let Font {
+            system,
+            buffer,
+            swash_cache,
+        } = self;
         buffer.set_metrics(system, Metrics::new(font_size, line_h));
         let mut buffer = buffer.borrow_with(system);
         let width = max.x;
@@ -96,43 +91,11 @@ impl Font {
             (color.a * 255.) as u8,
         );
 
-        let width = 10.;
-        // let height = line_h * buffer.layout_runs().count() as f32;
-        let height = 10.;
-        let mut canvas = vec![vec![None::<(u8, u8, u8, u8)>; height as usize]; width as usize];
-
-        buffer.draw(&mut res.swash_cache, text_color, |x, y, w, h, color| {
-            let a = color.a();
-            if a == 0 || x < 0 || y < 0 || y >= height as i32 || w != 1 || h != 1 {
-                // Ignore alphas of 0, or invalid x, y coordinates, or unimplemented sizes
-                return;
-            }
-            // if x as usize > canvas.len() - 1 {
-            //     let need = x as usize - canvas.len() + 1;
-            //     for _ in 0..need {
-            //         canvas.push(vec![None; height as usize]);
-            //     }
-            // }
-
-            let r = color.r();
-            let g = color.g();
-            let b = color.b();
-            // canvas[x as usize][y as usize] = Some((r, g, b, a));
-        });
-        let mut img =
-            DynamicImage::new(canvas.len() as u32, height as u32, image::ColorType::Rgba8);
-        // for (x, column) in canvas.iter().enumerate() {
-        //     for (y, pixel) in column.iter().enumerate() {
-        //         let (r, g, b, a) = pixel.unwrap_or((0, 0, 0, 0));
-        //         match &mut img {
-        //             DynamicImage::ImageRgba8(img) => {
-        //                 img.get_pixel_mut(x as u32, y as u32).0 = [r, g, b, a];
-        //             }
-        //             _ => panic!("AAaA (in text render :< )"),
-        //         }
-        //     }
-        // }
-        img
+        // Glyphs are not copied to image yet
+        let width = 10;
+        let height = 10;
+        buffer.draw(swash_cache, text_color, |_, _, _, _, _| {});
+        DynamicImage::new(width, height, image::ColorType::Rgba8)
     }
 }
 
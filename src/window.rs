@@ -1,7 +1,7 @@
 use std::{collections::HashMap, sync::Arc};
 use winit::window::{Window, WindowId};
 
-use crate::{render::surface::Surfaces, winit::WinitContext};
+use crate::{render::RenderContext, winit::WinitContext};
 
 pub type WindowAttributes = winit::window::WindowAttributes;
 pub type WindowEvent = winit::event::WindowEvent;
@@ -27,9 +27,10 @@ impl Windows {
         }
     }
 
-    pub fn delete(&mut self, window: CatWindow) {
+    /// Surface of window is removed from `context`
+    pub fn delete(&mut self, window: CatWindow, context: &RenderContext) {
         self.windows.remove(&window.id);
-        let mut surfaces = Surfaces::get();
+        let mut surfaces = context.surfaces();
         let surface = surfaces.get_surface_id_from_window(&window.id);
         if let Some(s) = surface {
             surfaces.delete_surface(s);
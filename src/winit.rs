@@ -8,6 +8,7 @@ use winit::{
 use crate::{
     app::CatApp,
     context::{AppContext, StaticContext},
+    render_thread::{render_frame, RenderThread},
     utils::timer::TimeStep,
     window::CatWindow,
 };
@@ -23,6 +24,8 @@ pub struct WinitContext<'a> {
 }
 
 pub(crate) struct WinitApp<A: CatApp> {
+    /// Joined first on drop, so last frame is rendered
+    render_thread: Option<RenderThread>,
     app: Option<A>,
     context: Option<StaticContext>,
     lag: f32,
@@ -31,6 +34,7 @@ pub(crate) struct WinitApp<A: CatApp> {
 impl<App: CatApp> Default for WinitApp<App> {
     fn default() -> Self {
         Self {
+            render_thread: None,
             app: Default::default(),
             context: Default::default(),
             lag: 0.,
@@ -44,6 +48,9 @@ impl<App: CatApp> ApplicationHandler for WinitApp<App> {
         let mut context = StaticContext::new();
         let mut app_context = AppContext::new(WinitContext { event_loop }, &mut context);
         self.app = Some(App::new(&mut app_context));
+        if App::config().render_thread {
+            self.render_thread = Some(RenderThread::spawn(context.share_renderer()));
+        }
         self.context = Some(context);
         self.step.delta();
     }
@@ -60,23 +67,61 @@ impl<App: CatApp> ApplicationHandler for WinitApp<App> {
                 self.lag += dl;
                 let fps = app_context.base.fps;
                 let ms_per_upd = 1. / fps as f32 * 1000.;
-                if self.lag >= ms_per_upd {
-                    self.app.as_mut().unwrap().update(&mut app_context, dl);
-
-                    if app_context.base.renderer.needs_exit {
-                        app_context.exit = true;
+                let is_lost = self.lag >= ms_per_upd && app_context.base.render_context.is_lost();
+                if is_lost {
+                    let (recovered, render_context) = app_context.base.with_renderer(|renderer| {
+                        (renderer.recover_device(), renderer.context().clone())
+                    });
+                    app_context.base.render_context = render_context;
+                    match recovered {
+                        Ok(()) => self
+                            .app
+                            .as_mut()
+                            .unwrap()
+                            .device_recreated(&mut app_context),
+                        Err(e) => {
+                            log::error!("Failed to create device again: {}", e);
+                            app_context.exit = true;
+                        }
+                    }
+                }
+                if self.lag >= ms_per_upd && !app_context.exit {
+                    let app = self.app.as_mut().unwrap();
+                    if let Some(shared) = &app_context.base.shared {
+                        app_context.base.thread_stats = shared.stats.lock().unwrap().clone();
                     }
+                    app.update(&mut app_context, dl);
 
-                    self.app
-                        .as_mut()
-                        .unwrap()
-                        .render(&mut app_context.base.renderer);
+                    match (&self.render_thread, &mut app_context.base.renderer) {
+                        (Some(render_thread), _) => {
+                            if let Some(frame) = app.extract(&mut app_context) {
+                                if !render_thread.submit(frame) {
+                                    log::error!("Render thread is stopped");
+                                    app_context.exit = true;
+                                }
+                            }
+                        }
+                        (None, Some(renderer)) => {
+                            if renderer.needs_exit {
+                                app_context.exit = true;
+                            }
+                            render_frame(renderer, |renderer| app.render(renderer));
+                        }
+                        (None, None) => unreachable!("Renderer is shared without render thread"),
+                    }
 
                     self.lag -= ms_per_upd;
                 }
             }
             WindowEvent::Resized(physical_size) => {
-                app_context.base.renderer.on_resize(&id, physical_size);
+                match (&mut app_context.base.renderer, &app_context.base.shared) {
+                    (Some(renderer), _) => renderer.on_resize(&id, physical_size),
+                    // Applied before next frame, render thread can draw to surface now
+                    (None, Some(shared)) => {
+                        shared.resizes.lock().unwrap().push((id, physical_size))
+                    }
+                    (None, None) => {}
+                }
             }
             _ => {}
         }